@@ -0,0 +1,58 @@
+use whale_rs::config;
+use whale_rs::history::{daemon, protocol};
+
+const USAGE: &str = "usage: whale-historyd [start | run | status | stop]
+
+  start   start the daemon in the background, if it isn't running already (default)
+  run     serve the history in the foreground until SIGTERM or SIGINT
+  status  print whether the daemon is running
+  stop    flush the history to disk and stop the daemon
+
+If the history is encrypted with a passphrase, `start` asks for it and hands it to the daemon.";
+
+fn main() {
+    let subcommand = std::env::args().nth(1).unwrap_or_else(|| String::from("start"));
+    let config = config::read_or_create_all_configs();
+    let socket_path = protocol::socket_path(&config);
+
+    let result = match subcommand.as_str() {
+        "start" => daemon::start(&config).map(|pid| {
+            println!("{} running, pid {}, socket {}", daemon::BINARY_NAME, pid, socket_path.display())
+        }),
+        "run" => daemon::run(&config),
+        "status" => match (daemon::running_pid(&config), daemon::status(&config)) {
+            (Some(pid), Ok(entries)) => {
+                println!(
+                    "{} running, pid {}, socket {}, protocol version {}, {} entries",
+                    daemon::BINARY_NAME,
+                    pid,
+                    socket_path.display(),
+                    protocol::PROTOCOL_VERSION,
+                    entries
+                );
+                Ok(())
+            }
+            (Some(pid), Err(e)) => {
+                println!("{} running, pid {}, but not answering: {}", daemon::BINARY_NAME, pid, e);
+                std::process::exit(1);
+            }
+            (None, _) => {
+                println!("{} not running", daemon::BINARY_NAME);
+                std::process::exit(1);
+            }
+        },
+        "stop" => daemon::stop(&config).map(|pid| match pid {
+            Some(pid) => println!("{} stopped, pid {}", daemon::BINARY_NAME, pid),
+            None => println!("{} not running", daemon::BINARY_NAME),
+        }),
+        _ => {
+            eprintln!("{}", USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if let Err(e) = result {
+        eprintln!("{}: {}", daemon::BINARY_NAME, e);
+        std::process::exit(1);
+    }
+}
@@ -1,9 +1,10 @@
 use std::cell::RefCell;
-use crate::{config::command, enums, hints, parser, state};
+use std::collections::HashMap;
+use crate::{config::command, enums, execution, hints, parser, state};
 use std::path;
 use std::rc::Rc;
 use crate::hints::Disregard;
-use crate::history::ux_layer;
+use crate::history::{stats, ux_layer};
 
 const BUFFER_LENGTH: usize = 8192;
 
@@ -59,14 +60,29 @@ pub struct InputBuffer {
     program_state: Rc<RefCell<state::ProgramState>>,
     argument_hints: Vec<(enums::ArgType, hints::Hint)>,
 
-    history: ux_layer::History,
+    /// Shared with the `history` reserved command.
+    history: Rc<RefCell<ux_layer::History>>,
+    /// Usage of executables, or of the words passed to an executable, for ranking hints.
+    usage: HashMap<Option<String>, Rc<HashMap<String, f64>>>,
+    /// Run counts of the executables, for suggesting command configs. Cleared along with `usage`.
+    executable_uses: Option<Rc<HashMap<String, u32>>>,
+    /// Rest of the most recent history entry starting with the buffer, drawn after the cursor.
+    suggestion: Option<String>,
+    /// Lists directories and rescans `PATH` off the input thread.
+    hint_worker: hints::worker::HintWorker,
+    /// The `arg_hint`s of the positional arguments the command still needs, drawn after the buffer.
+    missing_args: Vec<String>,
+    /// The required flags not given, drawn after `missing_args` as errors.
+    missing_flags: Vec<String>,
+    /// Arguments that are flags repeated or given with another they exclude, drawn as errors.
+    violations: Vec<usize>,
 
     curr_arg: usize,
 }
 
 impl InputBuffer {
     pub fn init(program_state: Rc<RefCell<state::ProgramState>>) -> Self {
-        let history = ux_layer::History::init(program_state.clone());
+        let history = Rc::new(RefCell::new(ux_layer::History::init(program_state.clone())));
         Self {
             buffer: ['\0'; BUFFER_LENGTH],
             input_length: 0,
@@ -76,6 +92,13 @@ impl InputBuffer {
             quote_locs: Vec::new(),
             argument_hints: Vec::new(),
             history,
+            usage: HashMap::new(),
+            executable_uses: None,
+            suggestion: None,
+            hint_worker: hints::worker::HintWorker::spawn(),
+            missing_args: Vec::new(),
+            missing_flags: Vec::new(),
+            violations: Vec::new(),
             program_state,
             curr_arg: 0,
         }
@@ -153,6 +176,32 @@ impl InputBuffer {
         }
     }
 
+    /// For the words that aren't hinted.
+    fn text_hint(&mut self, i: usize, valid: bool) {
+        if self.out_of_range_or_different(i, enums::ArgType::Text)
+            || !self.argument_hints[i].1.get_selection().is_empty() {
+            self.push_or_replace(i, (enums::ArgType::Text, hints::Hint::default()));
+        }
+        self.argument_hints[i].1.set_valid(valid);
+    }
+
+    /// For the words that are flags, or look like them, drawn as text but hinted with the flags
+    /// of `cmd`.
+    fn flag_hint(&mut self, i: usize, cmd: &command::ConfigCommand) {
+        let arg = self.get_buffer_str(self.arg_locs(i));
+        let ranking = self.hint_ranking(i);
+        let names = cmd.flag_names();
+        if self.out_of_range_or_different(i, enums::ArgType::Text)
+            || self.argument_hints[i].1.get_selection().iter().ne(names.iter().map(|(name, _)| name)) {
+            let hint = hints::executables::make_flags_hint(&arg, ranking, cmd);
+            self.push_or_replace(i, (enums::ArgType::Text, hint));
+        } else {
+            self.argument_hints[i].1.set_ranking(ranking);
+            hints::executables::update_executables_hint(&arg, &mut self.argument_hints[i].1);
+        }
+        self.argument_hints[i].1.set_valid(true);
+    }
+
     // TODO: Fix indexing to prevent these checks
     pub fn get_curr_hint_safe(&self) -> Option<(String, &hints::Hint)> {
         if self.num_args() != 0 {
@@ -211,40 +260,110 @@ impl InputBuffer {
         None
     }
 
+    /// How the hints of argument `arg_i` are ranked. The usage of the words passed to the first
+    /// argument is fetched from the history once per executable and command line.
+    fn hint_ranking(&mut self, arg_i: usize) -> hints::Ranking {
+        let executable = match arg_i {
+            0 => None,
+            _ => self.first_arg().as_deref().and_then(stats::executable_name).map(str::to_string),
+        };
+        let usage = match self.usage.get(&executable) {
+            Some(usage) => usage.clone(),
+            None => {
+                let usage = Rc::new(self.history.borrow_mut().usage(executable.as_deref()));
+                self.usage.insert(executable, usage.clone());
+                usage
+            }
+        };
+        hints::Ranking { match_mode: self.program_state.borrow().config.gui.hint_match, usage }
+    }
+
+    fn executable_uses(&mut self) -> Rc<HashMap<String, u32>> {
+        let history = &self.history;
+        self.executable_uses.get_or_insert_with(|| Rc::new(history.borrow_mut().executable_uses())).clone()
+    }
+
+    /// `value_start` is where the value begins within the argument, which is after the flag for
+    /// e.g. `--output=file`.
     fn process_hint<T>(
         &mut self,
         ind: usize,
+        value_start: usize,
         arg_type_func: impl Fn(&T) -> enums::ArgType,
         inlay_func: impl Fn(&T) -> &str,
         argument: &T,
     ) {
         let arg_type = arg_type_func(argument);
-        let arg = self.get_buffer_str(self.arg_locs(ind));
-        if self.out_of_range_or_different(ind, arg_type) {
-            let hint = match arg_type {
+        let arg = self.get_buffer_str(self.arg_locs(ind)).get(value_start..).unwrap_or_default().to_string();
+        let ranking = self.hint_ranking(ind);
+        if self.out_of_range_or_different(ind, arg_type.clone()) {
+            let inlay = Some(inlay_func(argument).to_string());
+            let hint = match &arg_type {
                 enums::ArgType::Executable => {
-                    hints::executables::make_executables_hint(&arg)
+                    self.hint_worker.refresh_executables();
+                    let uses = self.executable_uses();
+                    hints::executables::make_executables_hint(
+                        &arg,
+                        ranking,
+                        &self.program_state.borrow().config.commands,
+                        &uses,
+                    )
                 }
-                enums::ArgType::Path => hints::filesystem::make_directory_hints(
+                enums::ArgType::Text => hints::Hint::default(),
+                arg_type if arg_type.is_filesystem() => hints::filesystem::make_directory_hints(
                     self.arg_to_path(&arg),
-                    Some(inlay_func(argument).to_string()),
+                    inlay,
+                    ranking,
+                    arg_type,
+                    &mut self.hint_worker,
                 ),
-                enums::ArgType::Text => hints::Hint::default(),
+                arg_type @ enums::ArgType::Dynamic { .. } => {
+                    let program_state = self.program_state.borrow();
+                    hints::dynamic::make_dynamic_hint(
+                        arg_type,
+                        &arg,
+                        inlay,
+                        ranking,
+                        (&program_state.current_shell, &program_state.current_working_directory),
+                        &mut self.hint_worker,
+                    )
+                }
+                arg_type => hints::values::make_value_hint(arg_type, &arg, inlay, ranking),
             };
             self.push_or_replace(ind, (arg_type, hint));
         } else {
-            if arg_type == enums::ArgType::Path {
-                hints::filesystem::update_directory_hints(
-                    &self.arg_to_path(&arg),
-                    &mut self.argument_hints[ind].1,
-                );
-            } else if arg_type == enums::ArgType::Executable {
-                hints::executables::update_executables_hint(
+            self.argument_hints[ind].1.set_ranking(ranking);
+            self.argument_hints[ind].1.set_disregard(0);
+            match &arg_type {
+                enums::ArgType::Executable => hints::executables::update_executables_hint(
                     &arg,
                     &mut self.argument_hints[ind].1,
-                );
+                ),
+                // It may have been excess before
+                enums::ArgType::Text => self.argument_hints[ind].1.set_valid(true),
+                arg_type if arg_type.is_filesystem() => hints::filesystem::update_directory_hints(
+                    &self.arg_to_path(&arg),
+                    &mut self.argument_hints[ind].1,
+                    arg_type,
+                    &mut self.hint_worker,
+                ),
+                arg_type @ enums::ArgType::Dynamic { .. } => {
+                    let program_state = self.program_state.borrow();
+                    hints::dynamic::update_dynamic_hint(
+                        arg_type,
+                        &arg,
+                        &mut self.argument_hints[ind].1,
+                        (&program_state.current_shell, &program_state.current_working_directory),
+                        &mut self.hint_worker,
+                    )
+                }
+                arg_type => hints::values::update_value_hint(arg_type, &arg, &mut self.argument_hints[ind].1),
             }
         }
+        if value_start > 0 {
+            let hint = &mut self.argument_hints[ind].1;
+            hint.set_disregard(hint.disregard() + value_start);
+        }
     }
 
     pub fn first_arg(&self) -> Option<String> {
@@ -255,16 +374,49 @@ impl InputBuffer {
         }
     }
 
+    /// Takes in hints the worker finished since, returning whether they changed, in which case
+    /// `update_arguments` should run again to show them.
+    pub fn receive_hints(&mut self) -> bool {
+        let changed = self.hint_worker.receive();
+        if self.hint_worker.take_executables_changed() {
+            let candidates = hints::executables::candidates(&self.program_state.borrow().config.commands);
+            // Later executable hints are those of subcommands
+            if let Some((enums::ArgType::Executable, hint)) = self.argument_hints.first_mut() {
+                hint.set_selection(candidates);
+            }
+        }
+        changed
+    }
+
+    /// Whether the worker is still generating hints, so input should be polled rather than
+    /// waited on.
+    pub fn hints_pending(&self) -> bool {
+        self.hint_worker.is_pending()
+    }
+
     pub fn update_arguments(&mut self, arg_parser: &parser::ArgumentParser) {
+        self.receive_hints();
+        self.missing_args.clear();
+        self.missing_flags.clear();
+        self.violations.clear();
         let args = self.arg_locs_iterator()
             .map(|range| self.get_buffer_str(range))
             .collect::<Vec<_>>();
 
         if !arg_parser.has_command() {
+            let ranking = self.hint_ranking(0);
             if self.out_of_range_or_different(0, enums::ArgType::Executable) {
-                let hint = hints::executables::make_executables_hint(arg_parser.first_arg());
+                self.hint_worker.refresh_executables();
+                let uses = self.executable_uses();
+                let hint = hints::executables::make_executables_hint(
+                    arg_parser.first_arg(),
+                    ranking,
+                    &self.program_state.borrow().config.commands,
+                    &uses,
+                );
                 self.push_or_replace(0, (enums::ArgType::Executable, hint));
             } else {
+                self.argument_hints[0].1.set_ranking(ranking);
                 hints::executables::update_executables_hint(
                     arg_parser.first_arg(),
                     &mut self.argument_hints[0].1,
@@ -274,48 +426,93 @@ impl InputBuffer {
             for arg_i in 1..self.num_args() {
                 let arg = self.get_buffer_str(self.arg_locs(arg_i));
                 let path = self.arg_to_path(&arg);
+                let ranking = self.hint_ranking(arg_i);
                 if self.out_of_range_or_different(arg_i, enums::ArgType::Path) {
-                    let hint = hints::filesystem::make_directory_hints(path, None);
+                    let hint = hints::filesystem::make_directory_hints(
+                        path,
+                        None,
+                        ranking,
+                        &enums::ArgType::Path,
+                        &mut self.hint_worker,
+                    );
                     self.push_or_replace(arg_i, (enums::ArgType::Path, hint));
                     continue;
                 }
-                hints::filesystem::update_directory_hints(&path, &mut self.argument_hints[arg_i].1);
+                self.argument_hints[arg_i].1.set_ranking(ranking);
+                hints::filesystem::update_directory_hints(
+                    &path,
+                    &mut self.argument_hints[arg_i].1,
+                    &enums::ArgType::Path,
+                    &mut self.hint_worker,
+                );
             }
             return;
         }
 
-        let mut iter = parser::ArgumentIterator::new(&arg_parser);
+        let mut iter = parser::ArgumentIterator::new(arg_parser);
         iter.reinit(args);
         let mut i = 0;
-        for arg in iter {
+        while let Some(arg) = iter.next() {
             match arg {
-                parser::Argument::Other => {
-                    if self.out_of_range_or_different(i, enums::ArgType::Text) {
-                        let hint = hints::Hint::default();
-                        self.push_or_replace(i, (enums::ArgType::Text, hint));
-                    }
-                }
+                parser::Argument::Other => self.text_hint(i, true),
+                parser::Argument::Excess => self.text_hint(i, false),
                 parser::Argument::ArgFlag(arg_flag) => {
-                    if self.out_of_range_or_different(i, enums::ArgType::Text) {
-                        let hint = hints::Hint::default();
-                        self.push_or_replace(i, (enums::ArgType::Text, hint));
-                    }
+                    self.flag_hint(i, iter.cmd());
                     i += 1;
-                    self.process_hint(
+                    // The flag may be the last argument typed so far
+                    if i < self.num_args() {
+                        self.process_hint(
+                            i,
+                            0,
+                            command::FlagArgPair::arg_type,
+                            command::FlagArgPair::arg_hint,
+                            unsafe { &*arg_flag },
+                        )
+                    }
+                }
+                parser::Argument::Flags(group) => match group.arg_flag {
+                    Some((arg_flag, Some(value_start))) => self.process_hint(
                         i,
+                        value_start,
                         command::FlagArgPair::arg_type,
                         command::FlagArgPair::arg_hint,
                         unsafe { &*arg_flag },
-                    )
-                }
-                parser::Argument::Flag(_flag) => {
-                    if self.out_of_range_or_different(i, enums::ArgType::Text) {
-                        self.push_or_replace(i, (enums::ArgType::Text, hints::Hint::default()));
+                    ),
+                    Some((arg_flag, None)) => {
+                        self.flag_hint(i, iter.cmd());
+                        i += 1;
+                        if i < self.num_args() {
+                            self.process_hint(
+                                i,
+                                0,
+                                command::FlagArgPair::arg_type,
+                                command::FlagArgPair::arg_hint,
+                                unsafe { &*arg_flag },
+                            )
+                        }
+                    }
+                    None => self.flag_hint(i, iter.cmd()),
+                },
+                parser::Argument::Flag(_) | parser::Argument::UnknownFlag => self.flag_hint(i, iter.cmd()),
+                parser::Argument::EndOfFlags => self.text_hint(i, true),
+                parser::Argument::Subcommand(parent) => {
+                    let parent = unsafe { &*parent };
+                    let arg = self.get_buffer_str(self.arg_locs(i));
+                    let ranking = self.hint_ranking(i);
+                    let names = parent.subcommand_names();
+                    if self.out_of_range_or_different(i, enums::ArgType::Executable)
+                        || self.argument_hints[i].1.get_selection() != names {
+                        let hint = hints::executables::make_subcommands_hint(&arg, ranking, parent);
+                        self.push_or_replace(i, (enums::ArgType::Executable, hint));
+                    } else {
+                        self.argument_hints[i].1.set_ranking(ranking);
+                        hints::executables::update_executables_hint(&arg, &mut self.argument_hints[i].1);
                     }
                 }
                 parser::Argument::Arg(arg) => {
                     self.process_hint(
                         i,
+                        0,
                         command::SingleArg::arg_type,
                         command::SingleArg::arg_hint,
                         unsafe { &*arg },
@@ -324,6 +521,16 @@ impl InputBuffer {
             }
             i += 1;
         }
+        self.violations = iter.violations().to_vec();
+        self.missing_flags = iter.missing_flags().into_iter().map(str::to_string).collect();
+        self.missing_args = iter.missing_args()
+            .iter()
+            .filter(|arg| !arg.arg_hint.is_empty())
+            .map(|arg| match arg.variadic {
+                true => format!("<{}...>", arg.arg_hint),
+                false => format!("<{}>", arg.arg_hint),
+            })
+            .collect();
     }
 
     pub fn update(&mut self) {
@@ -477,19 +684,20 @@ impl InputBuffer {
     }
 
     pub fn insert_str_main_cursor(&mut self, s: &str) {
-        if self.input_length + s.len() > BUFFER_LENGTH {
+        let len = s.chars().count();
+        if self.input_length + len > BUFFER_LENGTH {
             return; // Buffer is full
         }
 
         // Move all chars after cursor to the right
         for i in (self.main_cursor.position..self.input_length).rev() {
-            self.buffer[i + s.len()] = self.buffer[i];
+            self.buffer[i + len] = self.buffer[i];
         }
         for (i, c) in s.chars().enumerate() {
             self.buffer[self.main_cursor.position + i] = c;
         }
-        self.main_cursor.position += s.len();
-        self.input_length += s.len();
+        self.main_cursor.position += len;
+        self.input_length += len;
     }
 
     pub fn del_betw_curs(&mut self) {
@@ -527,37 +735,96 @@ impl InputBuffer {
         self.secondary_cursor.active = false;
     }
 
+    fn set_from_history(&mut self, cmd: &str) {
+        let len = cmd.chars().count().min(BUFFER_LENGTH);
+        for (i, c) in cmd.chars().take(len).enumerate() {
+            self.buffer[i] = c;
+        }
+        self.input_length = len;
+        self.main_cursor.position = len;
+        self.secondary_cursor.active = false;
+    }
+
     pub fn history_older(&mut self) {
-        if let Some(older) = self
-            .history
-            .get_older_history(&self.buffer[..self.input_length])
-        {
-            let older = older.command();
-            for (i, c) in older.chars().enumerate() {
-                self.buffer[i] = c;
-            }
-            self.input_length = older.len();
-            self.main_cursor.position = older.len();
-            self.secondary_cursor.active = false;
+        let older = self.history.borrow_mut().get_older_history(&self.buffer[..self.input_length]);
+        if let Some(older) = older {
+            self.set_from_history(older.command());
         }
     }
 
     pub fn history_newer(&mut self) {
-        if let Some(newer) = self.history.get_newer_history() {
-            let newer = newer.command();
-            for (i, c) in newer.chars().enumerate() {
-                self.buffer[i] = c;
+        let newer = self.history.borrow_mut().get_newer_history();
+        if let Some(newer) = newer {
+            self.set_from_history(newer.command());
+        }
+    }
+
+    pub fn get_suggestion(&self) -> Option<&str> {
+        self.suggestion.as_deref()
+    }
+
+    pub fn get_missing_args(&self) -> &[String] {
+        &self.missing_args
+    }
+
+    pub fn get_missing_flags(&self) -> &[String] {
+        &self.missing_flags
+    }
+
+    pub fn is_violation(&self, arg: usize) -> bool {
+        self.violations.contains(&arg)
+    }
+
+    /// Suggestions are only looked up while the cursor is at the end of the buffer.
+    pub fn update_suggestion(&mut self) {
+        self.suggestion = None;
+        let enabled = self.program_state.borrow().config.history.autosuggestions;
+        if !enabled
+            || self.input_length == 0
+            || self.secondary_cursor.active
+            || self.main_cursor.position != self.input_length {
+            return;
+        }
+
+        let typed = self.get_buffer().iter().collect::<String>();
+        if let Some(history_entry) = self.history.borrow_mut().suggest(&typed) {
+            let rest = &history_entry.command()[typed.len()..];
+            if !rest.is_empty() {
+                self.suggestion = Some(rest.to_string());
             }
-            self.input_length = newer.len();
-            self.main_cursor.position = newer.len();
-            self.secondary_cursor.active = false;
         }
     }
 
-    pub fn history_push_current(&mut self) {
+    /// Returns false if there was no suggestion to accept.
+    pub fn accept_suggestion(&mut self) -> bool {
+        match self.suggestion.take() {
+            Some(suggestion) => {
+                self.insert_str_main_cursor(&suggestion);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The shell's one connection to the history, so that `history` commands run in it reuse it
+    /// rather than connecting again.
+    pub fn history(&self) -> Rc<RefCell<ux_layer::History>> {
+        self.history.clone()
+    }
+
+    pub fn history_push_current(
+        &mut self,
+        exit_status: Option<execution::StatusCode>,
+        timing: Option<execution::CommandTiming>,
+    ) {
         if self.len() == 0 { return; }
+        // The command changes what has been used most
+        self.usage.clear();
+        self.executable_uses = None;
         let cmd = self.get_buffer().iter().collect::<String>();
-        self.history.add_to_history(cmd).unwrap();
+        if let Err(e) = self.history.borrow_mut().add_to_history(cmd, exit_status, timing) {
+            eprintln!("Failed to add the command to the history: {}", e);
+        }
     }
 }
 
@@ -723,22 +990,26 @@ mod tests {
                     arg_type: enums::ArgType::Executable,
                     arg_hint: "exe".to_string(),
                     arg_pos: 1,
+                    ..Default::default()
                 },
                 command::SingleArg {
                     arg_type: enums::ArgType::Path,
                     arg_hint: "src".to_string(),
                     arg_pos: 1,
+                    ..Default::default()
                 },
                 command::SingleArg {
                     arg_type: enums::ArgType::Path,
                     arg_hint: "dst".to_string(),
                     arg_pos: 2,
+                    ..Default::default()
                 },
             ],
             flags: vec![
                 command::Flag {
                     flag_name: "-f".to_string(),
                     flag_to: "--force".to_string(),
+                    info: command::FlagInfo::default(),
 
                     execute_before: None,
                     execute_after: None,
@@ -748,6 +1019,7 @@ mod tests {
                 command::FlagArgPair {
                     flag_name: "-h".to_string(),
                     flag_to: "--help".to_string(),
+                    info: command::FlagInfo::default(),
 
                     arg_type: enums::ArgType::Executable,
                     arg_hint: "subcommand".to_string(),
@@ -756,6 +1028,9 @@ mod tests {
                     execute_after: None,
                 }
             ],
+            subcommands: vec![],
+            exclusive_flags: vec![],
+            skip_history: false,
         };
         program_state.borrow_mut().config.commands = vec![mv_cmd];
         let mut buffer = super::InputBuffer::init(program_state);
@@ -781,5 +1056,238 @@ mod tests {
         assert_eq!(buffer.argument_hints[4].0, enums::ArgType::Executable);
         assert_eq!(buffer.argument_hints[5].0, enums::ArgType::Path);
     }
-}
 
+    #[test]
+    fn test_subcommand_arguments() {
+        let program_state = Rc::new(RefCell::new(default_program_state()));
+        let mut arg_parser = parser::ArgumentParser::new(program_state.clone());
+        let mut git_cmd: command::ConfigCommand = toml::from_str(r#"
+            [[subcommand]]
+            name = "add"
+            [[subcommand.single_arg]]
+            arg_type = "Path"
+            arg_hint = "file"
+            arg_pos = 1
+
+            [[subcommand]]
+            name = "commit"
+            [[subcommand.flag_arg_pair]]
+            flag_name = "-m"
+            flag_to = "-m"
+            arg_type = "Text"
+        "#).unwrap();
+        git_cmd.exe_name = "git".to_string();
+        command::normalize(&mut git_cmd).unwrap();
+        program_state.borrow_mut().config.commands = vec![git_cmd];
+        let mut buffer = super::InputBuffer::init(program_state);
+
+        buffer.insert_str_main_cursor("git co");
+        buffer.update();
+        arg_parser.reinit(buffer.first_arg());
+        buffer.update_arguments(&arg_parser);
+        assert_eq!(buffer.argument_hints[1].0, enums::ArgType::Executable);
+        assert_eq!(buffer.argument_hints[1].1.get_selection(), ["add", "commit"]);
+        assert_eq!(buffer.argument_hints[1].1.last_closest_match().map(String::as_str), Some("commit"));
+
+        buffer.clear_all();
+        buffer.insert_str_main_cursor("git add -m x");
+        buffer.update();
+        arg_parser.reinit(buffer.first_arg());
+        buffer.update_arguments(&arg_parser);
+        // `-m` belongs to `commit`, so is an unknown flag rather than taking its argument
+        assert_eq!(buffer.argument_hints[2].0, enums::ArgType::Text);
+        assert_eq!(buffer.argument_hints[3].0, enums::ArgType::Path);
+    }
+
+    #[test]
+    fn test_flag_groups() {
+        let program_state = Rc::new(RefCell::new(default_program_state()));
+        let mut arg_parser = parser::ArgumentParser::new(program_state.clone());
+        let mut ls_cmd: command::ConfigCommand = toml::from_str(r#"
+            [[single_arg]]
+            arg_type = "Path"
+            arg_hint = "file"
+            arg_pos = 1
+
+            [[flag]]
+            flag_name = "-a"
+            flag_to = "-a"
+
+            [[flag]]
+            flag_name = "-l"
+            flag_to = "-l"
+
+            [[flag_arg_pair]]
+            flag_name = "--output"
+            flag_to = "-o"
+            arg_type = "Path"
+
+            [[flag_arg_pair]]
+            flag_name = "-o"
+            flag_to = "-o"
+            arg_type = "Path"
+        "#).unwrap();
+        ls_cmd.exe_name = "ls".to_string();
+        command::normalize(&mut ls_cmd).unwrap();
+        program_state.borrow_mut().config.commands = vec![ls_cmd];
+        let mut buffer = super::InputBuffer::init(program_state);
+
+        buffer.insert_str_main_cursor("ls -la --output=src -ao out -- -l");
+        buffer.update();
+        arg_parser.reinit(buffer.first_arg());
+        buffer.update_arguments(&arg_parser);
+        assert_eq!(buffer.argument_hints[1].0, enums::ArgType::Text);
+        // The value after `=` is hinted by itself
+        assert_eq!(buffer.argument_hints[2].0, enums::ArgType::Path);
+        assert_eq!(buffer.argument_hints[2].1.disregard(), "--output=".len());
+        assert_eq!(buffer.argument_hints[3].0, enums::ArgType::Text);
+        assert_eq!(buffer.argument_hints[4].0, enums::ArgType::Path);
+        assert_eq!(buffer.argument_hints[5].0, enums::ArgType::Text);
+        // After `--`, even what looks like a flag is the file
+        assert_eq!(buffer.argument_hints[6].0, enums::ArgType::Path);
+    }
+
+    #[test]
+    fn test_variadic_arguments() {
+        let program_state = Rc::new(RefCell::new(default_program_state()));
+        let mut arg_parser = parser::ArgumentParser::new(program_state.clone());
+        let mut cp_cmd: command::ConfigCommand = toml::from_str(r#"
+            [[flag_arg_pair]]
+            flag_name = "-t"
+            flag_to = "-t"
+            arg_type = "Directory"
+
+            [[single_arg]]
+            arg_type = "Text"
+            arg_hint = "src"
+            arg_pos = 1
+            variadic = true
+            max = 2
+
+            [[single_arg]]
+            arg_type = "Path"
+            arg_hint = "dst"
+            last = true
+        "#).unwrap();
+        cp_cmd.exe_name = "cp".to_string();
+        command::normalize(&mut cp_cmd).unwrap();
+        program_state.borrow_mut().config.commands = vec![cp_cmd];
+        let mut buffer = super::InputBuffer::init(program_state);
+
+        buffer.insert_str_main_cursor("cp a");
+        buffer.update();
+        arg_parser.reinit(buffer.first_arg());
+        buffer.update_arguments(&arg_parser);
+        // The last word only goes to `dst` once `src` has one
+        assert_eq!(buffer.argument_hints[1].0, enums::ArgType::Text);
+        assert_eq!(buffer.get_missing_args(), ["<dst>"]);
+
+        buffer.insert_str_main_cursor(" b -t x c");
+        buffer.update();
+        arg_parser.reinit(buffer.first_arg());
+        buffer.update_arguments(&arg_parser);
+        assert_eq!(buffer.argument_hints[2].0, enums::ArgType::Text);
+        assert_eq!(buffer.argument_hints[4].0, enums::ArgType::Directory);
+        assert_eq!(buffer.argument_hints[5].0, enums::ArgType::Path);
+        assert!(buffer.get_missing_args().is_empty());
+
+        buffer.clear_all();
+        buffer.insert_str_main_cursor("cp a b c d");
+        buffer.update();
+        arg_parser.reinit(buffer.first_arg());
+        buffer.update_arguments(&arg_parser);
+        // `src` takes at most two
+        assert!(buffer.argument_hints[2].1.is_valid());
+        assert_eq!(buffer.argument_hints[3].0, enums::ArgType::Text);
+        assert!(!buffer.argument_hints[3].1.is_valid());
+        assert_eq!(buffer.argument_hints[4].0, enums::ArgType::Path);
+
+        let mut mv_cmd: command::ConfigCommand = toml::from_str(r#"
+            [[single_arg]]
+            arg_type = "Path"
+            arg_hint = "dst"
+            last = true
+
+            [[single_arg]]
+            arg_type = "Directory"
+            arg_hint = "dir"
+            last = true
+        "#).unwrap();
+        mv_cmd.exe_name = "mv".to_string();
+        assert!(command::normalize(&mut mv_cmd).is_err());
+    }
+
+    #[test]
+    fn test_flag_rules() {
+        let program_state = Rc::new(RefCell::new(default_program_state()));
+        let mut arg_parser = parser::ArgumentParser::new(program_state.clone());
+        let mut ls_cmd: command::ConfigCommand = toml::from_str(r#"
+            exclusive_flags = [["--quiet", "-v"], ["-1", "-l"]]
+
+            [[flag]]
+            flag_name = "-q"
+            flag_to = "-q"
+            aliases = ["--quiet"]
+            description = "Print nothing"
+
+            [[flag]]
+            flag_name = "-v"
+            flag_to = "-v"
+            aliases = ["--verbose"]
+            repeatable = true
+
+            [[flag]]
+            flag_name = "-a"
+            flag_to = "-a"
+            implies = ["-l"]
+
+            [[flag]]
+            flag_name = "-l"
+            flag_to = "-l"
+
+            [[flag]]
+            flag_name = "-1"
+            flag_to = "-1"
+
+            [[flag_arg_pair]]
+            flag_name = "-o"
+            flag_to = "-o"
+            arg_type = "Text"
+            required = true
+        "#).unwrap();
+        ls_cmd.exe_name = "ls".to_string();
+        command::normalize(&mut ls_cmd).unwrap();
+        program_state.borrow_mut().config.commands = vec![ls_cmd];
+        let mut buffer = super::InputBuffer::init(program_state);
+        let mut parse = |buffer: &mut super::InputBuffer, s: &str| {
+            buffer.clear_all();
+            buffer.insert_str_main_cursor(s);
+            buffer.update();
+            arg_parser.reinit(buffer.first_arg());
+            buffer.update_arguments(&arg_parser);
+        };
+
+        parse(&mut buffer, "ls -v --verbose -a -la");
+        assert!(!buffer.is_violation(2));
+        assert!(buffer.is_violation(4));
+        assert_eq!(buffer.get_missing_flags(), ["-o"]);
+
+        parse(&mut buffer, "ls -q -vo x");
+        assert!(buffer.is_violation(1) && buffer.is_violation(2));
+        assert!(buffer.get_missing_flags().is_empty());
+
+        // Given in the same word is still a conflict
+        parse(&mut buffer, "ls -qv -o x");
+        assert!(buffer.is_violation(1) && !buffer.is_violation(2));
+
+        // `-l` is implied, so excluded by `-1`
+        parse(&mut buffer, "ls -a -1 -o x");
+        assert!(buffer.is_violation(1) && buffer.is_violation(2));
+
+        parse(&mut buffer, "ls --qu");
+        let hint = &buffer.argument_hints[1].1;
+        assert_eq!(hint.last_closest_match().map(String::as_str), Some("--quiet"));
+        let i = hint.get_selection().iter().position(|name| name == "--quiet").unwrap();
+        assert_eq!(hint.description(i), Some("Print nothing"));
+    }
+}
@@ -10,11 +10,12 @@ pub type CommandString = Option<String>;
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConfigCommand {
     /// The command name that should trigger this `Command` being used. I.e. `mv`. Note that this
-    /// is generated using the file name.
-    #[serde(skip, default)]
+    /// is generated using the file name, except for subcommands, which give it as `name`.
+    #[serde(default, rename = "name")]
     pub exe_name: String,
     /// A direct mapping from the string that trigged using this command, to what should actually be
     /// executed. If left blank, the `exe_name` will be used.
+    #[serde(default)]
     pub exe_to: String,
 
     /// A command that should be executed before executing the entered command.
@@ -22,12 +23,26 @@ pub struct ConfigCommand {
     /// A command that should be executed after executing the entered command.
     pub execute_after: CommandString,
 
-    #[serde(rename = "single_arg")]
+    #[serde(default, rename = "single_arg")]
     pub args: Vec<SingleArg>,
-    #[serde(rename = "flag")]
+    #[serde(default, rename = "flag")]
     pub flags: Vec<Flag>,
-    #[serde(rename = "flag_arg_pair")]
+    #[serde(default, rename = "flag_arg_pair")]
     pub arg_flags: Vec<FlagArgPair>,
+    /// E.g. the `commit` of `git commit`, chosen by the first positional argument. Each has its own
+    /// arguments, whose `arg_pos` count from the subcommand, and is executed as the `exe_to` of its
+    /// parent followed by its own. Its `execute_before` runs after its parent's, and its
+    /// `execute_after` before its parent's.
+    #[serde(default, rename = "subcommand", skip_serializing_if = "Vec::is_empty")]
+    pub subcommands: Vec<ConfigCommand>,
+    /// Groups of flags of which at most one may be given, e.g. `[["-q", "-v"]]`. Flags and flags
+    /// taking an argument may be mixed, by any of their names.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclusive_flags: Vec<Vec<String>>,
+
+    /// Never save this command in the history, e.g. for a password manager's CLI.
+    #[serde(default)]
+    pub skip_history: bool,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
@@ -37,24 +52,78 @@ pub struct SingleArg {
     /// typing in the <src> field.
     pub arg_hint: String,
     /// Since arguments are unnamed parameters, and `.toml` files could be subject to reording, an
-    /// explicit `arg_pos` must be given.
+    /// explicit `arg_pos` must be given, unless it is the `last`.
+    #[serde(default)]
     pub arg_pos: usize,
+    /// Takes every positional argument that follows, up to `max`, e.g. the `<paths...>` of `rm`. Any
+    /// argument after it must be the `last`.
+    #[serde(default)]
+    pub variadic: bool,
+    /// May be left out, e.g. the `[files...]` of `tar <archive> [files...]`.
+    #[serde(default)]
+    pub optional: bool,
+    /// Always the final positional argument, however many `variadic` takes before it, e.g. the
+    /// `<dst>` of `cp <src...> <dst>`.
+    #[serde(default)]
+    pub last: bool,
+    /// How many arguments a `variadic` one takes: at least one unless `optional`, and any number
+    /// by default.
+    pub min: Option<usize>,
+    pub max: Option<usize>,
 }
 
 impl SingleArg {
     pub fn arg_type(&self) -> ArgType {
-        self.arg_type
+        self.arg_type.clone()
     }
 
     pub fn arg_hint(&self) -> &str {
         &self.arg_hint
     }
+
+    pub fn min_count(&self) -> usize {
+        match (self.optional, self.variadic) {
+            (true, _) => 0,
+            (false, true) => self.min.unwrap_or(1),
+            (false, false) => 1,
+        }
+    }
+
+    /// `None` if there is no limit.
+    pub fn max_count(&self) -> Option<usize> {
+        match self.variadic {
+            true => self.max,
+            false => Some(1),
+        }
+    }
+}
+
+/// What `Flag` and `FlagArgPair` have in common, besides their names.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FlagInfo {
+    /// Other names for the flag, e.g. `--verbose` for `-v`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<String>,
+    /// Shown next to the flag when choosing one from the dropdown.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    /// May be given more than once, e.g. `-v -v`, which is otherwise an error.
+    #[serde(default)]
+    pub repeatable: bool,
+    /// Must be given, or be implied by another flag.
+    #[serde(default)]
+    pub required: bool,
+    /// Names of flags that this one turns on as well, e.g. `-a` implying `-l`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub implies: Vec<String>,
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Flag {
     pub flag_name: String,
     pub flag_to: String,
+    #[serde(flatten)]
+    pub info: FlagInfo,
 
     pub execute_before: CommandString,
     pub execute_after: CommandString,
@@ -64,12 +133,18 @@ impl Flag {
     pub fn flag_name(&self) -> &str {
         &self.flag_name
     }
+
+    pub fn has_name(&self, name: &str) -> bool {
+        self.flag_name == name || self.info.aliases.iter().any(|alias| alias == name)
+    }
 }
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FlagArgPair {
     pub flag_name: String,
     pub flag_to: String,
+    #[serde(flatten)]
+    pub info: FlagInfo,
 
     pub arg_type: ArgType,
     #[serde(default)]
@@ -84,8 +159,12 @@ impl FlagArgPair {
         &self.flag_name
     }
 
+    pub fn has_name(&self, name: &str) -> bool {
+        self.flag_name == name || self.info.aliases.iter().any(|alias| alias == name)
+    }
+
     pub fn arg_type(&self) -> ArgType {
-        self.arg_type
+        self.arg_type.clone()
     }
 
     pub fn arg_hint(&self) -> &str {
@@ -107,39 +186,115 @@ pub fn read_commands(command_dir: &path::PathBuf) -> Vec<ConfigCommand> {
         let path = entry.path();
         if path.is_file() && path.extension().unwrap() == ffi::OsStr::new("toml") {
             let file_name = path.file_stem().unwrap().to_str().unwrap();
-            let mut command: ConfigCommand = read_config(&path).unwrap();
-            command.exe_name = file_name.to_string();
-
-            { // `SingleArgs`
-                let main_arg = SingleArg {
-                    arg_type: ArgType::Executable,
-                    arg_hint: "".to_string(),
-                    arg_pos: 0,
-                };
-                command.args.push(main_arg);
-                command.args.sort_by(|a, b| a.arg_pos.cmp(&b.arg_pos));
-                // assert no duplicate arg_pos
-                let mut prev_pos = if command.args.len() > 0 {
-                    command.args[0].arg_pos
-                } else {
-                    0
-                };
-                for arg in command.args.iter().skip(1) {
-                    assert!(arg.arg_pos > prev_pos);
-                    prev_pos = arg.arg_pos;
-                }
+            // A broken command config shouldn't keep the shell from starting, so it is left out
+            let command = read_config(&path).and_then(|mut command: ConfigCommand| {
+                command.exe_name = file_name.to_string();
+                normalize(&mut command)?;
+                Ok(command)
+            });
+            match command {
+                Ok(command) => commands.push(command),
+                Err(e) => eprintln!("Skipping command config {}: {}", path.display(), e),
             }
-
-            command.flags.sort_by(|a, b| a.flag_name.cmp(&b.flag_name));
-            command
-                .arg_flags
-                .sort_by(|a, b| a.flag_name.cmp(&b.flag_name));
-            commands.push(command);
         }
     }
     commands
 }
 
+/// Fills in what was left out and sorts what is searched, for the command and its subcommands.
+/// Fails if two arguments are at the same position, or more than one is the `last`.
+pub(crate) fn normalize(command: &mut ConfigCommand) -> anyhow::Result<()> {
+    if command.exe_to.is_empty() {
+        command.exe_to = command.exe_name.clone();
+    }
+
+    { // `SingleArgs`
+        let main_arg = SingleArg {
+            arg_type: ArgType::Executable,
+            arg_hint: "".to_string(),
+            arg_pos: 0,
+            ..Default::default()
+        };
+        command.args.push(main_arg);
+        command.args.sort_by_key(|arg| (arg.last, arg.arg_pos));
+        // no duplicate arg_pos, and at most one argument that is the `last`
+        let mut prev_pos = if !command.args.is_empty() {
+            command.args[0].arg_pos
+        } else {
+            0
+        };
+        for arg in command.args.iter().skip(1).filter(|arg| !arg.last) {
+            if arg.arg_pos <= prev_pos {
+                return Err(anyhow::anyhow!("`{}` has two arguments at {}", command.exe_name, arg.arg_pos));
+            }
+            prev_pos = arg.arg_pos;
+        }
+        if command.args.iter().filter(|arg| arg.last).count() > 1 {
+            return Err(anyhow::anyhow!("`{}` has two last arguments", command.exe_name));
+        }
+    }
+
+    command.flags.sort_by(|a, b| a.flag_name.cmp(&b.flag_name));
+    command
+        .arg_flags
+        .sort_by(|a, b| a.flag_name.cmp(&b.flag_name));
+
+    // Flags are referred to by their `flag_name` from here on, rather than any alias
+    let canonical = |name: &String| -> String {
+        command.flags.iter().find(|flag| flag.has_name(name)).map(|flag| &flag.flag_name)
+            .or_else(|| command.arg_flags.iter().find(|flag| flag.has_name(name)).map(|flag| &flag.flag_name))
+            .unwrap_or(name)
+            .clone()
+    };
+    let exclusive_flags = command.exclusive_flags.iter()
+        .map(|group| group.iter().map(canonical).collect())
+        .collect();
+    let implies = command.flags.iter().map(|flag| &flag.info)
+        .chain(command.arg_flags.iter().map(|flag| &flag.info))
+        .map(|info| info.implies.iter().map(canonical).collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+    command.exclusive_flags = exclusive_flags;
+    let infos = command.flags.iter_mut().map(|flag| &mut flag.info)
+        .chain(command.arg_flags.iter_mut().map(|flag| &mut flag.info));
+    for (info, implies) in infos.zip(implies) {
+        info.implies = implies;
+    }
+
+    command.subcommands.sort_by(|a, b| a.exe_name.cmp(&b.exe_name));
+    for subcommand in &mut command.subcommands {
+        normalize(subcommand)?;
+    }
+    Ok(())
+}
+
+impl ConfigCommand {
+    pub fn subcommand(&self, name: &str) -> Option<&ConfigCommand> {
+        self.subcommands
+            .binary_search_by(|subcommand| subcommand.exe_name.as_str().cmp(name))
+            .ok()
+            .map(|i| &self.subcommands[i])
+    }
+
+    pub fn subcommand_names(&self) -> Vec<String> {
+        self.subcommands.iter().map(|subcommand| subcommand.exe_name.clone()).collect()
+    }
+
+    /// Every name and alias of the flags, sorted, with the description of the flag each names.
+    pub fn flag_names(&self) -> Vec<(String, String)> {
+        let infos = self.flags.iter().map(|flag| (&flag.flag_name, &flag.info))
+            .chain(self.arg_flags.iter().map(|arg_flag| (&arg_flag.flag_name, &arg_flag.info)));
+        let mut names = vec![];
+        for (name, info) in infos {
+            for name in std::iter::once(name).chain(&info.aliases) {
+                names.push((name.clone(), info.description.clone()));
+            }
+        }
+        names.sort();
+        names.dedup_by(|a, b| a.0 == b.0);
+        names
+    }
+}
+
 fn read_config<P: AsRef<path::Path>, T: for<'de> Deserialize<'de>>(
     path: P,
 ) -> anyhow::Result<T> {
@@ -160,15 +315,21 @@ fn create_defaults(directory: &path::PathBuf) -> anyhow::Result<Vec<ConfigComman
                 arg_type: ArgType::Path,
                 arg_hint: "src".to_string(),
                 arg_pos: 1,
+                variadic: true,
+                ..Default::default()
             },
             SingleArg {
                 arg_type: ArgType::Path,
                 arg_hint: "dst".to_string(),
-                arg_pos: 2,
+                last: true,
+                ..Default::default()
             },
         ],
         flags: vec![],
         arg_flags: vec![],
+        subcommands: vec![],
+        exclusive_flags: vec![],
+        skip_history: false,
     };
 
     write_command_config(directory, &move_default)?;
@@ -0,0 +1,31 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Notification {
+    None,
+    /// Rings the terminal bell.
+    Bell,
+    /// Desktop notification understood by iTerm2, kitty, WezTerm and friends.
+    Osc9,
+    /// Desktop notification understood by urxvt and VTE based terminals.
+    Osc777,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ConfigExecution {
+    /// Commands that run for longer than this show their duration in the prompt.
+    pub show_duration_after_ms: u64,
+    /// Commands that run for longer than this trigger the `notification`.
+    pub notify_after_ms: u64,
+    pub notification: Notification,
+}
+
+impl Default for ConfigExecution {
+    fn default() -> Self {
+        Self {
+            show_duration_after_ms: 5_000,
+            notify_after_ms: 30_000,
+            notification: Notification::Bell,
+        }
+    }
+}
@@ -1,7 +1,11 @@
 use serde::{Deserialize, Serialize};
+use crate::fuzzy::MatchMode;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConfigGUI {
+    /// How the typed argument is matched against the hints in the dropdown and table.
+    #[serde(default)]
+    pub hint_match: MatchMode,
     pub table: ConfigTableGUI,
     pub dropdown: ConfigDropdownGUI,
 }
@@ -9,6 +13,7 @@ pub struct ConfigGUI {
 impl Default for ConfigGUI {
     fn default() -> Self {
         Self {
+            hint_match: MatchMode::default(),
             table: ConfigTableGUI::default(),
             dropdown: ConfigDropdownGUI::default(),
         }
@@ -2,14 +2,95 @@ use super::parse_path_buf;
 
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
+use crate::fuzzy::MatchMode;
+use crate::history::ScopeKind;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConfigHistory {
     /// The `history_fp` is appended to the `data_dir` found in `ConfigCore`.
     #[serde(deserialize_with = "parse_path_buf")]
     pub history_fp: PathBuf,
+    /// The history file is compacted once it grows past this, see `history::retention`.
     pub max_file_size_bytes: u64,
-    pub tcp_port: u16,
+    /// Most entries to keep when compacting, `0` for no limit.
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    /// Entries older than this are dropped when compacting, `0` for no limit.
+    #[serde(default)]
+    pub max_age_days: u64,
+    /// Keep only the newest entry of a command when compacting.
+    #[serde(default = "default_dedupe")]
+    pub dedupe: bool,
+    /// Commands run at least this many times survive `max_entries` and `max_age_days`. `0` turns
+    /// this off.
+    #[serde(default = "default_keep_frequent_uses")]
+    pub keep_frequent_uses: u32,
+    /// Overrides the socket in the per-user runtime directory, see `history::protocol`.
+    #[serde(default)]
+    pub socket_path: Option<PathBuf>,
+    /// Start `whale-historyd` when no data layer is running, rather than serving the history from
+    /// a thread of the shell that found it missing.
+    #[serde(default = "default_spawn_daemon")]
+    pub spawn_daemon: bool,
+    /// How the typed text filters the entries stepped through with `ArrowUp`/`ArrowDown`.
+    /// Only `Prefix` and `Substring` make sense here.
+    #[serde(default = "default_navigation_match")]
+    pub navigation_match: MatchMode,
+    /// Show the most recent history entry starting with the typed text after the cursor.
+    #[serde(default = "default_autosuggestions")]
+    pub autosuggestions: bool,
+    /// The scope navigation and search start in, changed with `Ctrl+G`.
+    #[serde(default)]
+    pub default_scope: ScopeKind,
+    /// Leave commands typed with a leading space out of the history, as bash's `ignorespace`.
+    #[serde(default = "default_ignore_space")]
+    pub ignore_space: bool,
+    /// Regular expressions, commands matching any of which are left out of the history.
+    #[serde(default)]
+    pub ignore_patterns: Vec<String>,
+    /// Replace likely secrets, e.g. `password=...` or AWS keys, before commands are saved. Redacted
+    /// entries are never offered as autosuggestions.
+    #[serde(default = "default_redact_secrets")]
+    pub redact_secrets: bool,
+    /// Encrypt the history file, see `history::encryption`. The key is derived from `key_file`
+    /// if set, otherwise from a passphrase asked for when the data layer starts.
+    #[serde(default)]
+    pub encrypt: bool,
+    /// A file only its owner can read, holding a passphrase or random bytes. May start with `~/`.
+    #[serde(default)]
+    pub key_file: Option<PathBuf>,
+}
+
+fn default_navigation_match() -> MatchMode {
+    MatchMode::Prefix
+}
+
+fn default_autosuggestions() -> bool {
+    true
+}
+
+fn default_max_entries() -> usize {
+    10_000
+}
+
+fn default_dedupe() -> bool {
+    true
+}
+
+fn default_keep_frequent_uses() -> u32 {
+    10
+}
+
+fn default_ignore_space() -> bool {
+    true
+}
+
+fn default_redact_secrets() -> bool {
+    true
+}
+
+fn default_spawn_daemon() -> bool {
+    true
 }
 
 impl Default for ConfigHistory {
@@ -17,7 +98,20 @@ impl Default for ConfigHistory {
         Self {
             history_fp: PathBuf::from("whale.history"),
             max_file_size_bytes: 256 * 1024,
-            tcp_port: 5456,
+            max_entries: default_max_entries(),
+            max_age_days: 0,
+            dedupe: default_dedupe(),
+            keep_frequent_uses: default_keep_frequent_uses(),
+            socket_path: None,
+            spawn_daemon: default_spawn_daemon(),
+            navigation_match: default_navigation_match(),
+            autosuggestions: default_autosuggestions(),
+            default_scope: ScopeKind::default(),
+            ignore_space: default_ignore_space(),
+            ignore_patterns: vec![],
+            redact_secrets: default_redact_secrets(),
+            encrypt: false,
+            key_file: None,
         }
     }
 }
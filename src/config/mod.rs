@@ -2,6 +2,7 @@ extern crate dotenv;
 
 pub mod command;
 pub mod core;
+pub mod execution;
 pub mod gui;
 pub mod history;
 pub mod theme;
@@ -13,6 +14,7 @@ use std::io::{Read, Write};
 pub struct FullConfig {
     pub core: core::ConfigCore,
     pub history: history::ConfigHistory,
+    pub execution: execution::ConfigExecution,
     pub theme: theme::ConfigTheme,
     pub gui: gui::ConfigGUI,
     pub commands: Vec<command::ConfigCommand>,
@@ -23,6 +25,7 @@ impl Default for FullConfig {
         Self {
             core: core::ConfigCore::default(),
             history: history::ConfigHistory::default(),
+            execution: execution::ConfigExecution::default(),
             theme: theme::ConfigTheme::default(),
             gui: gui::ConfigGUI::default(),
             commands: vec![],
@@ -62,6 +65,9 @@ pub fn read_or_create_all_configs() -> FullConfig {
     let cfg_history: history::ConfigHistory =
         read_or_create_config(&config_dir.join("history.toml")).unwrap_or_default();
 
+    let cfg_execution: execution::ConfigExecution =
+        read_or_create_config(config_dir.join("execution.toml")).unwrap_or_default();
+
     let mut cfg_theme: theme::ConfigTheme =
         read_or_create_config(&config_dir.join("theme.toml")).unwrap_or_default();
     cfg_theme.generate_escape_sequences();
@@ -75,6 +81,7 @@ pub fn read_or_create_all_configs() -> FullConfig {
     FullConfig {
         core: cfg_core,
         history: cfg_history,
+        execution: cfg_execution,
         theme: cfg_theme,
         gui: cfg_gui,
         commands: cfg_commands,
@@ -109,14 +109,65 @@ impl FromStr for Shell {
     }
 }
 
-/// This determines the hints that will be generated.
-#[derive(Debug, PartialEq, Clone, Default, Copy, Serialize, Deserialize)]
+/// This determines the hints that will be generated, and how the argument is validated.
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
 pub enum ArgType {
     /// Provides suggestions based on current and surrounding directories.
     Path,
-    /// Provides suggestions based on executable list generated at program startup.
+    /// Like `Path`, but only suggests directories.
+    Directory,
+    /// Like `Path`, but only suggests directories and files with one of `extensions`, or any file
+    /// if there are none. E.g. `{ File = { extensions = ["toml"] } }`.
+    File {
+        #[serde(default)]
+        extensions: Vec<String>,
+    },
+    /// Provides suggestions based on the executables on `PATH`, see `hints::executables`.
     Executable,
+    /// One of `values`, e.g. `{ Choice = { values = ["start", "stop"] } }`.
+    Choice {
+        values: Vec<String>,
+    },
+    /// A whole number within the optional bounds, which are inclusive.
+    Integer {
+        #[serde(default)]
+        min: Option<i64>,
+        #[serde(default)]
+        max: Option<i64>,
+    },
+    /// The name of an environment variable, with or without a leading `$`.
+    EnvVar,
+    /// A user from `/etc/passwd`, or a uid.
+    User,
+    /// A group from `/etc/group`, or a gid.
+    Group,
+    /// A host from `/etc/hosts` or `~/.ssh/config`, optionally preceded by `user@`.
+    Hostname,
+    /// The id of a running process.
+    Pid,
+    /// A signal name, with or without `SIG` and a leading `-`, or number.
+    Signal,
+    /// The lines `command` prints when run by the current shell in the current directory, e.g.
+    /// `{ Dynamic = { command = "git branch --format='%(refname:short)'", cache_secs = 5 } }`. The
+    /// output is reused for `cache_secs`. A command that fails or takes too long gives no hints.
+    Dynamic {
+        command: String,
+        #[serde(default = "default_cache_secs")]
+        cache_secs: u64,
+    },
     /// Does not provide any suggestions.
     #[default]
     Text,
 }
+
+fn default_cache_secs() -> u64 {
+    5
+}
+
+impl ArgType {
+    /// Whether the argument names a file or directory, so its hints come from
+    /// `hints::filesystem`.
+    pub fn is_filesystem(&self) -> bool {
+        matches!(self, Self::Path | Self::Directory | Self::File { .. })
+    }
+}
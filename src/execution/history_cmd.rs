@@ -0,0 +1,441 @@
+//! The `history` reserved command.
+
+use std::cell::RefCell;
+use std::{fs, time};
+use std::rc::Rc;
+use std::str::FromStr;
+use crate::history::{export, import, ux_layer, HistoryFilter, HistoryScope};
+use crate::hints::executables;
+use crate::{state, utils};
+use super::{get_nth, remove_quotes, running, ReservedFuncParams, ReservedFuncReturn, StatusCode};
+
+const USAGE: &str = "Usage:
+  history [list] [filters]                    list entries with their indices
+  history delete <index|start-end>...         remove entries, as numbered by `history list`
+  history compact                             apply the retention settings in history.toml now
+  history stats [-n <count>]                  usage statistics for the current history scope
+  history export <jsonl|csv|bash> <file> [filters]
+  history import <bash|zsh|fish> [file]
+Filters:
+  <text>, --contains <text>   command contains the text
+  --cwd <dir>, --here         run in the directory, or below it
+  --since <time>, --until <time>
+                              a unix timestamp, yyyy-mm-dd, or e.g. 30m, 2h, 3d, 1w ago
+  --status <code>, --failed   exit status
+  --session <id>, --this-session
+  -n, --limit <count>         only the newest entries, 50 by default when listing";
+
+const DEFAULT_LIST_LIMIT: usize = 50;
+const DEFAULT_STATS_TOP: usize = 10;
+
+/// Prints `message` in the error style and returns the status to finish the command with.
+fn print_error(program_state: &Rc<RefCell<state::ProgramState>>, message: &str) -> StatusCode {
+    crate::gui::output_str(&program_state.borrow().config.theme.error.normal, message);
+    println!();
+    1
+}
+
+pub fn history_cmd(params: ReservedFuncParams) -> ReservedFuncReturn {
+    let (program_state, buf) = params;
+    let args = (2..=buf.num_args())
+        .filter_map(|n| get_nth(n, buf))
+        .map(|arg| remove_quotes(&arg).to_string())
+        .collect::<Vec<String>>();
+
+    let history = buf.history();
+    let history = &mut history.borrow_mut();
+
+    running::begin_output();
+    let status = match args.first().map(String::as_str) {
+        None => list_cmd(&program_state, history, &args),
+        Some("list") => list_cmd(&program_state, history, &args[1..]),
+        Some("delete") => delete_cmd(&program_state, history, &args[1..]),
+        Some("compact") => compact_cmd(&program_state, history),
+        Some("stats") => stats_cmd(&program_state, history, &args[1..]),
+        Some("export") => export_cmd(&program_state, history, &args[1..]),
+        Some("import") => import_cmd(&program_state, history, &args[1..]),
+        Some("help") | Some("--help") => {
+            println!("{}", USAGE);
+            0
+        }
+        Some(_) => list_cmd(&program_state, history, &args),
+    };
+    let new_line = running::end_output();
+    ReservedFuncReturn::Printed(new_line, status)
+}
+
+/// A unix timestamp, `yyyy-mm-dd` (UTC, as shown by `history list`), or a duration ago such as
+/// `30m`, `2h`, `3d` or `1w`.
+fn parse_time(s: &str) -> Option<u64> {
+    if let Ok(timestamp) = s.parse::<u64>() {
+        return Some(timestamp);
+    }
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return date.and_hms_opt(0, 0, 0).map(|t| t.and_utc().timestamp().max(0) as u64);
+    }
+
+    let unit = match s.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    let amount = s[..s.len() - 1].parse::<u64>().ok()?;
+    let now = time::SystemTime::now().duration_since(time::UNIX_EPOCH).ok()?.as_secs();
+    Some(now.saturating_sub(amount * unit))
+}
+
+/// Parses the filter flags described in `USAGE`, along with `--limit`.
+fn parse_filter(
+    program_state: &Rc<RefCell<state::ProgramState>>,
+    args: &[String],
+) -> Result<(HistoryFilter, Option<usize>), String> {
+    let mut filter = HistoryFilter::default();
+    let mut limit = None;
+    let mut words = vec![];
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |flag: &str| {
+            args.next().cloned().ok_or_else(|| format!("{} needs a value", flag))
+        };
+        let parse_time_of = |flag: &str, value: String| {
+            parse_time(&value).ok_or_else(|| format!("Invalid time for {}: {}", flag, value))
+        };
+        match arg.as_str() {
+            "--contains" => words.push(value(arg)?),
+            "--cwd" => {
+                let cwd = program_state.borrow().current_working_directory.clone();
+                let dir = value(arg)?;
+                filter.cwd = Some(match dir.as_str() {
+                    "." => cwd,
+                    dir => utils::resolve_path(dir, &cwd),
+                });
+            }
+            "--here" => filter.cwd = Some(program_state.borrow().current_working_directory.clone()),
+            "--since" => filter.since = Some(parse_time_of(arg, value(arg)?)?),
+            "--until" => filter.until = Some(parse_time_of(arg, value(arg)?)?),
+            "--status" => {
+                let status = value(arg)?;
+                filter.exit_status = Some(status.parse().map_err(|_| format!("Invalid status: {}", status))?);
+            }
+            "--failed" => filter.failed = true,
+            "--session" => filter.session_id = Some(value(arg)?),
+            "--this-session" => filter.session_id = Some(program_state.borrow().session_id.clone()),
+            "-n" | "--limit" => {
+                let count = value(arg)?;
+                limit = Some(count.parse().map_err(|_| format!("Invalid limit: {}", count))?);
+            }
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: {}", flag)),
+            word => words.push(word.to_string()),
+        }
+    }
+    if !words.is_empty() {
+        filter.contains = Some(words.join(" "));
+    }
+    Ok((filter, limit))
+}
+
+/// `history [list] [filters]`
+fn list_cmd(
+    program_state: &Rc<RefCell<state::ProgramState>>,
+    history: &mut ux_layer::History,
+    args: &[String],
+) -> StatusCode {
+    let (filter, limit) = match parse_filter(program_state, args) {
+        Ok(filter) => filter,
+        Err(e) => return print_error(program_state, &e),
+    };
+    let entries = match history.query(filter, Some(limit.unwrap_or(DEFAULT_LIST_LIMIT))) {
+        Ok((generation, entries)) => {
+            program_state.borrow_mut().listed_history_generation = Some(generation);
+            entries
+        }
+        Err(e) => return print_error(program_state, &format!("Failed to read history: {}", e)),
+    };
+
+    let index_width = entries.last().map_or(1, |(i, _)| i.to_string().len());
+    let p_state = program_state.borrow();
+    let theme = &p_state.config.theme;
+    for (i, history_entry) in entries {
+        let info = format!(
+            "{:>width$}  {}  ",
+            i,
+            utils::format_timestamp(history_entry.timestamp()),
+            width = index_width
+        );
+        crate::gui::output_str(&theme.console_secondary.normal, &info);
+        match history_entry.exit_status() {
+            Some(status) if status != 0 => crate::gui::output_str(&theme.error.normal, history_entry.command()),
+            _ => print!("{}", history_entry.command()),
+        }
+        println!();
+    }
+    0
+}
+
+/// `history delete <index|start-end>...`
+fn delete_cmd(
+    program_state: &Rc<RefCell<state::ProgramState>>,
+    history: &mut ux_layer::History,
+    args: &[String],
+) -> StatusCode {
+    let parse_range = |arg: &String| -> Option<(usize, usize)> {
+        match arg.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (start.parse().ok()?, end.parse().ok()?);
+                (start <= end).then_some((start, end))
+            }
+            None => arg.parse().ok().map(|i| (i, i)),
+        }
+    };
+    let ranges = match args.iter().map(parse_range).collect::<Option<Vec<(usize, usize)>>>() {
+        Some(ranges) if !ranges.is_empty() => ranges,
+        _ => return print_error(program_state, "Usage: history delete <index|start-end>..."),
+    };
+
+    // Without a listing there is nothing to tell whether the indices still mean the same entries
+    let generation = match program_state.borrow().listed_history_generation {
+        Some(generation) => generation,
+        None => return print_error(program_state, "Run `history list` first, to see the indices to delete"),
+    };
+    match history.delete(Some(generation), ranges) {
+        Ok(removed) => {
+            println!("Deleted {} entries", removed);
+            0
+        }
+        Err(e) => print_error(program_state, &format!("Failed to delete history: {}", e)),
+    }
+}
+
+/// `history compact`
+fn compact_cmd(program_state: &Rc<RefCell<state::ProgramState>>, history: &mut ux_layer::History) -> StatusCode {
+    match history.compact() {
+        Ok(removed) => {
+            println!("Removed {} entries", removed);
+            0
+        }
+        Err(e) => print_error(program_state, &format!("Failed to compact history: {}", e)),
+    }
+}
+
+/// Prints `rows` in aligned columns under `title`. Cells that start with a digit are right
+/// aligned, so counts and rates line up.
+fn print_grid(program_state: &Rc<RefCell<state::ProgramState>>, title: &str, headers: &[&str], rows: &[Vec<String>]) {
+    if rows.is_empty() {
+        return;
+    }
+    let p_state = program_state.borrow();
+    let theme = &p_state.config.theme;
+
+    let mut widths = headers.iter().map(|header| header.chars().count()).collect::<Vec<usize>>();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let format_row = |cells: &mut dyn Iterator<Item=&str>| {
+        cells
+            .zip(&widths)
+            .map(|(cell, width)| match cell.starts_with(|c: char| c.is_ascii_digit()) {
+                true => format!("{:>width$}", cell, width = width),
+                false => format!("{:<width$}", cell, width = width),
+            })
+            .collect::<Vec<String>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+
+    println!();
+    crate::gui::output_str(&theme.console_main.highlighted, title);
+    println!();
+    crate::gui::output_str(&theme.console_main.normal, &format_row(&mut headers.iter().copied()));
+    println!();
+    for row in rows {
+        crate::gui::output_str(&theme.console_secondary.normal, &format_row(&mut row.iter().map(String::as_str)));
+        println!();
+    }
+}
+
+/// `history stats [-n <count>]`
+fn stats_cmd(
+    program_state: &Rc<RefCell<state::ProgramState>>,
+    history: &mut ux_layer::History,
+    args: &[String],
+) -> StatusCode {
+    let top = match args {
+        [] => DEFAULT_STATS_TOP,
+        [flag, count] if flag == "-n" || flag == "--limit" => match count.parse() {
+            Ok(count) => count,
+            Err(_) => return print_error(program_state, &format!("Invalid limit: {}", count)),
+        },
+        _ => return print_error(program_state, "Usage: history stats [-n <count>]"),
+    };
+
+    let scope = HistoryScope::resolve(&program_state.borrow());
+    let history_stats = match history.stats(scope, top) {
+        Ok(history_stats) => history_stats,
+        Err(e) => return print_error(program_state, &format!("Failed to read history: {}", e)),
+    };
+
+    let configured = |name: &str| {
+        program_state.borrow().config.commands.iter().any(|command| command.exe_name == name)
+    };
+    let uses_of = |name: &str| {
+        history_stats.executables.iter().find(|e| e.name == name).map_or(0, |e| e.uses)
+    };
+    let percentage = |rate: Option<f64>| rate.map_or(String::from("-"), |rate| format!("{:.0}%", rate * 100.0));
+
+    println!(
+        "{} commands in the {} history, {:.1} characters long on average",
+        history_stats.total,
+        program_state.borrow().history_scope.name(),
+        history_stats.average_length
+    );
+
+    let rows = history_stats.commands
+        .iter()
+        .map(|(command, uses)| vec![uses.to_string(), command.replace('\n', " ")])
+        .collect::<Vec<_>>();
+    print_grid(program_state, "Most used commands", &["uses", "command"], &rows);
+
+    let rows = history_stats.executables
+        .iter()
+        .take(top)
+        .map(|executable| vec![
+            executable.uses.to_string(),
+            executable.name.clone(),
+            percentage(executable.failure_rate()),
+            String::from(if configured(&executable.name) { "yes" } else { "-" }),
+        ])
+        .collect::<Vec<_>>();
+    print_grid(program_state, "Most used executables", &["uses", "executable", "failed", "config"], &rows);
+
+    let rows = history_stats.directories
+        .iter()
+        .map(|(dir, uses)| vec![uses.to_string(), dir.display().to_string()])
+        .collect::<Vec<_>>();
+    print_grid(program_state, "Directories", &["uses", "directory"], &rows);
+
+    let mut hours = (0..24).filter(|hour| history_stats.hours[*hour] > 0).collect::<Vec<usize>>();
+    hours.sort_by_key(|hour| std::cmp::Reverse(history_stats.hours[*hour]));
+    let busiest = hours.first().map_or(1, |hour| history_stats.hours[*hour]);
+    let rows = hours
+        .into_iter()
+        .take(top.min(24))
+        .map(|hour| vec![
+            format!("{:02}:00", hour),
+            history_stats.hours[hour].to_string(),
+            "#".repeat((history_stats.hours[hour] * 20).div_ceil(busiest) as usize),
+        ])
+        .collect::<Vec<_>>();
+    print_grid(program_state, "Busiest hours (UTC)", &["hour", "commands", ""], &rows);
+
+    let mut command_uses = program_state.borrow().config.commands
+        .iter()
+        .map(|command| (uses_of(&command.exe_name), command.exe_name.clone()))
+        .collect::<Vec<(u32, String)>>();
+    command_uses.sort_by_key(|(uses, _)| std::cmp::Reverse(*uses));
+    let rows = command_uses
+        .into_iter()
+        .map(|(uses, name)| vec![uses.to_string(), name, String::from(if uses == 0 { "never used" } else { "" })])
+        .collect::<Vec<_>>();
+    print_grid(program_state, "Command configs", &["uses", "command", ""], &rows);
+
+    let commands_dir = program_state.borrow().config.core.config_dir.join("commands");
+    let rows = history_stats.executables
+        .iter()
+        .filter(|executable| executable.uses >= executables::SUGGEST_CONFIG_MIN_USES)
+        .filter(|executable| executables::lacks_config(&executable.name, &program_state.borrow().config.commands))
+        .map(|executable| vec![
+            executable.uses.to_string(),
+            executable.name.clone(),
+            commands_dir.join(format!("{}.toml", executable.name)).display().to_string(),
+        ])
+        .collect::<Vec<_>>();
+    print_grid(program_state, "Suggested command configs", &["uses", "executable", "add"], &rows);
+    0
+}
+
+/// `history export <jsonl|csv|bash> <file> [filters]`
+fn export_cmd(
+    program_state: &Rc<RefCell<state::ProgramState>>,
+    history: &mut ux_layer::History,
+    args: &[String],
+) -> StatusCode {
+    let (format, file) = match (args.first(), args.get(1)) {
+        (Some(format), Some(file)) => (format, file),
+        _ => return print_error(program_state, "Usage: history export <jsonl|csv|bash> <file> [filters]"),
+    };
+    let format = match export::ExportFormat::from_str(format) {
+        Ok(format) => format,
+        Err(e) => return print_error(program_state, &e.to_string()),
+    };
+    let (filter, limit) = match parse_filter(program_state, &args[2..]) {
+        Ok(filter) => filter,
+        Err(e) => return print_error(program_state, &e),
+    };
+
+    let entries = match history.query(filter, limit) {
+        Ok((_, entries)) => entries.into_iter().map(|(_, history_entry)| history_entry).collect::<Vec<_>>(),
+        Err(e) => return print_error(program_state, &format!("Failed to read history: {}", e)),
+    };
+
+    let path = utils::resolve_path(file, &program_state.borrow().current_working_directory);
+    match fs::write(&path, export::export(format, &entries)) {
+        Ok(()) => {
+            println!("Exported {} entries to {}", entries.len(), path.display());
+            0
+        }
+        Err(e) => print_error(program_state, &format!("Failed to write {}: {}", path.display(), e)),
+    }
+}
+
+/// `history import <bash|zsh|fish> [file]`
+fn import_cmd(
+    program_state: &Rc<RefCell<state::ProgramState>>,
+    history: &mut ux_layer::History,
+    args: &[String],
+) -> StatusCode {
+    let source = match args.first().map(|shell| import::Source::from_str(shell)) {
+        Some(Ok(source)) => source,
+        Some(Err(e)) => return print_error(program_state, &e.to_string()),
+        None => return print_error(program_state, USAGE),
+    };
+    let path = {
+        let cwd = program_state.borrow().current_working_directory.clone();
+        match (args.get(1), utils::home_dir()) {
+            (Some(file), _) => utils::resolve_path(file, &cwd),
+            (None, Some(home)) => source.default_path(&home),
+            (None, None) => return print_error(program_state, "No home directory, pass the file"),
+        }
+    };
+
+    let contents = match fs::read(&path) {
+        Ok(contents) => contents,
+        Err(e) => {
+            let message = format!("Failed to read {}: {}", path.display(), e);
+            return print_error(program_state, &message);
+        }
+    };
+    let history_entries = source.parse(&contents);
+    let read = history_entries.len();
+
+    match history.import(history_entries) {
+        Ok((added, skipped)) => {
+            println!(
+                "Read {} entries from {}, added {}, {} already in the history, {} left out by the privacy settings",
+                read,
+                path.display(),
+                added,
+                skipped,
+                read - added - skipped
+            );
+            0
+        }
+        Err(e) => print_error(program_state, &format!("Failed to import history: {}", e)),
+    }
+}
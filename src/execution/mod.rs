@@ -1,32 +1,86 @@
 use std::cell::RefCell;
-use std::path;
+use std::{fs, path};
 use std::process::exit;
 use std::rc::Rc;
-use crate::{buffer, enums, state};
+use crate::{buffer, enums, hints, state, tabular};
 
 use std::str::FromStr;
+use std::time;
+use serde::{Deserialize, Serialize};
 
 pub mod running;
+pub mod history_cmd;
+#[cfg(target_os = "linux")]
+pub mod signals;
 
 pub type StatusCode = i32;
 
+/// How long a command took. `cpu` is the user plus system time of the child, as reported by
+/// `wait4`, and is zero on platforms where that isn't available.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CommandTiming {
+    pub wall: time::Duration,
+    pub cpu: time::Duration,
+}
+
+impl std::ops::AddAssign for CommandTiming {
+    fn add_assign(&mut self, rhs: Self) {
+        self.wall += rhs.wall;
+        self.cpu += rhs.cpu;
+    }
+}
+
 pub type ReservedFuncParams<'a> = (
     Rc<RefCell<state::ProgramState>>,
     &'a buffer::InputBuffer
 );
 
+/// How a child process stopped running.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChildStatus {
+    Exited(StatusCode),
+    Signaled(i32),
+    Stopped(i32),
+}
+
+impl ChildStatus {
+    /// Follows the shell convention of `128 + n` for processes killed by signal `n`.
+    pub fn code(&self) -> Option<StatusCode> {
+        match self {
+            Self::Exited(code) => Some(*code),
+            Self::Signaled(signal) => Some(128 + signal),
+            Self::Stopped(_) => None,
+        }
+    }
+}
+
 pub enum ReservedFuncReturn {
     Ok,
     Status(StatusCode),
     DontExecute(StatusCode),
+    /// The command wrote to the terminal (between `running::begin_output` and
+    /// `running::end_output`), so the prompt must be moved to the given line.
+    Printed(running::NewTerminalLine, StatusCode),
 }
 
 const RESERVED_COMMANDS: &[(&str, fn(ReservedFuncParams) -> ReservedFuncReturn)] = &[
     ("exit", exit_cmd),
     ("cd", cd_cmd),
-    ("use", use_cmd)
+    ("use", use_cmd),
+    ("table", table_cmd),
+    ("fg", fg_cmd),
+    ("history", history_cmd::history_cmd),
+    ("rehash", rehash_cmd),
 ];
 
+pub fn is_reserved(name: &str) -> bool {
+    RESERVED_COMMANDS.iter().any(|(cmd, _)| *cmd == name)
+}
+
+pub fn reserved_names() -> impl Iterator<Item=&'static str> {
+    RESERVED_COMMANDS.iter().map(|(cmd, _)| *cmd)
+}
+
 fn get_nth(n: usize, buf: &buffer::InputBuffer) -> Option<String> {
     if n == 0 {
         panic!("This is 0-indexed");
@@ -92,3 +146,77 @@ fn use_cmd(params: ReservedFuncParams) -> ReservedFuncReturn {
     }
     ReservedFuncReturn::DontExecute(0)
 }
+
+/// Scans `PATH` for executables again. This happens by itself when `PATH` or one of its
+/// directories changes, so it is only needed when a binary was replaced in place.
+fn rehash_cmd(_params: ReservedFuncParams) -> ReservedFuncReturn {
+    let index = hints::executables::rehash();
+    running::begin_output();
+    println!("rehash: {} executables in {} directories on PATH", index.len(), index.num_dirs());
+    let new_line = running::end_output();
+    ReservedFuncReturn::Printed(new_line, 0)
+}
+
+/// Resumes the most recently suspended command in the foreground.
+fn fg_cmd(params: ReservedFuncParams) -> ReservedFuncReturn {
+    let (program_state, _buf) = params;
+    let job = program_state.borrow_mut().suspended_jobs.pop();
+    match job {
+        None => {
+            eprintln!("fg: no suspended jobs");
+            ReservedFuncReturn::DontExecute(1)
+        }
+        Some((pid, command)) => {
+            running::begin_output();
+            println!("{}", command);
+            let status = running::resume_job(&program_state, pid, &command);
+            let new_line = running::end_output();
+            ReservedFuncReturn::Printed(new_line, status.code().unwrap_or(0))
+        }
+    }
+}
+
+/// `table <file>` or `table <command...>`. Parses the file, or the command's stdout, into a
+/// `tabular::Table` which `main` then opens in `gui::records::RecordsGUI`.
+fn table_cmd(params: ReservedFuncParams) -> ReservedFuncReturn {
+    let (program_state, buf) = params;
+    let args = (2..=buf.num_args())
+        .filter_map(|n| get_nth(n, buf))
+        .collect::<Vec<String>>();
+    if args.is_empty() {
+        eprintln!("Usage: table <file> | table <command>");
+        return ReservedFuncReturn::DontExecute(1);
+    }
+
+    let cwd = program_state.borrow().current_working_directory.clone();
+    let file = cwd.join(remove_quotes(&args[0]));
+    let input = if args.len() == 1 && file.is_file() {
+        fs::read_to_string(file).map_err(anyhow::Error::from)
+    } else {
+        let mut command = program_state.borrow().current_shell.to_exec();
+        command.arg(args.join(" ")).current_dir(&cwd);
+        command
+            .output()
+            .map(|output| String::from_utf8_lossy(&output.stdout).to_string())
+            .map_err(anyhow::Error::from)
+    };
+
+    let input = match input {
+        Ok(input) => input,
+        Err(e) => {
+            eprintln!("Failed to read table input: {}", e);
+            return ReservedFuncReturn::DontExecute(1);
+        }
+    };
+
+    match tabular::parse(&input) {
+        Some(table) => {
+            program_state.borrow_mut().pending_table = Some(table);
+            ReservedFuncReturn::DontExecute(0)
+        }
+        None => {
+            eprintln!("Could not find a table in the output");
+            ReservedFuncReturn::DontExecute(1)
+        }
+    }
+}
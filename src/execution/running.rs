@@ -1,6 +1,8 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use crate::{ansi, buffer, enums, parser, state};
+use std::{process, time};
+use crate::{ansi, buffer, parser, state, utils};
+use crate::config::execution::{ConfigExecution, Notification};
 
 fn reserved_commands(
     program_state: Rc<RefCell<state::ProgramState>>,
@@ -15,7 +17,7 @@ fn reserved_commands(
     None
 }
 
-fn parse_execution_cmd(args: &Vec<String>, command: &str) -> String {
+fn parse_execution_cmd(args: &[String], command: &str) -> String {
     let to_usize = |s: &str| -> usize {
         s.parse::<usize>().unwrap()
     };
@@ -56,11 +58,204 @@ fn parse_execution_cmd(args: &Vec<String>, command: &str) -> String {
 
 pub type NewTerminalLine = u16;
 
+#[cfg(target_os = "linux")]
+#[allow(clippy::zombie_processes)] // Reaped by `wait4` in `signals::wait_foreground`
+fn spawn_and_wait(mut command: process::Command) -> (super::ChildStatus, time::Duration, u32) {
+    super::signals::prepare_child(&mut command);
+    let child = command.spawn().unwrap();
+    let (status, cpu) = super::signals::wait_foreground(child.id());
+    (status, cpu, child.id())
+}
+
+#[cfg(target_os = "windows")]
+fn spawn_and_wait(mut command: process::Command) -> (super::ChildStatus, time::Duration, u32) {
+    let mut child = command.spawn().unwrap();
+    let exit = child.wait().unwrap();
+    (super::ChildStatus::Exited(exit.code().unwrap_or(-1)), time::Duration::ZERO, child.id())
+}
+
+#[cfg(target_os = "linux")]
+fn signal_name(signal: i32) -> String {
+    super::signals::signal_name(signal)
+}
+
+#[cfg(target_os = "windows")]
+fn signal_name(signal: i32) -> String {
+    format!("signal {}", signal)
+}
+
+/// Tells the user when a command didn't exit by itself, and remembers stopped commands for `fg`.
+fn report_status(
+    program_state: &Rc<RefCell<state::ProgramState>>,
+    command: &str,
+    pid: u32,
+    status: super::ChildStatus,
+) {
+    let message = match status {
+        super::ChildStatus::Exited(_) => return,
+        super::ChildStatus::Signaled(signal) => {
+            format!("whale: `{}` terminated by {}", command, signal_name(signal))
+        }
+        super::ChildStatus::Stopped(signal) => {
+            program_state
+                .borrow_mut()
+                .suspended_jobs
+                .push((pid, command.to_string()));
+            format!(
+                "whale: `{}` ({}) suspended by {}, resume it with `fg`",
+                command,
+                pid,
+                signal_name(signal)
+            )
+        }
+    };
+    println!();
+    crate::gui::output_str(&program_state.borrow().config.theme.error.normal, &message);
+    println!();
+}
+
+/// Prepares the terminal for output that isn't drawn by whale, e.g. a child process.
+pub fn begin_output() {
+    println!();
+    ansi::move_to_column(0);
+    ansi::erase_screen_from_cursor();
+    ansi::flush();
+
+    crossterm::terminal::disable_raw_mode().unwrap();
+}
+
+/// Undoes `begin_output`, returning the line the prompt should now be drawn on.
+pub fn end_output() -> NewTerminalLine {
+    crossterm::terminal::enable_raw_mode().unwrap();
+    ansi::cursor_pos().unwrap().1
+}
+
+/// Continues a job that was stopped while in the foreground. Should be wrapped by
+/// `begin_output` and `end_output`.
+#[cfg(target_os = "linux")]
+pub fn resume_job(
+    program_state: &Rc<RefCell<state::ProgramState>>,
+    pid: u32,
+    command: &str,
+) -> super::ChildStatus {
+    let (status, _cpu) = super::signals::resume(pid);
+    report_status(program_state, command, pid, status);
+    status
+}
+
+#[cfg(target_os = "windows")]
+pub fn resume_job(
+    _program_state: &Rc<RefCell<state::ProgramState>>,
+    _pid: u32,
+    _command: &str,
+) -> super::ChildStatus {
+    eprintln!("Job control is not supported on this platform");
+    super::ChildStatus::Exited(1)
+}
+
+/// Lets the user know a long running command has finished, as configured in `ConfigExecution`.
+fn notify_if_slow(config: &ConfigExecution, command: &str, timing: &super::CommandTiming) {
+    if timing.wall < time::Duration::from_millis(config.notify_after_ms) {
+        return;
+    }
+    let message = format!(
+        "{} finished after {}",
+        command,
+        utils::format_duration(timing.wall)
+    );
+    match config.notification {
+        Notification::None => (),
+        Notification::Bell => print!("\x07"),
+        Notification::Osc9 => print!("{}]9;{}\x07", ansi::ESCAPE_CODE, message),
+        Notification::Osc777 => print!("{}]777;notify;whale;{}\x07", ansi::ESCAPE_CODE, message),
+    }
+    ansi::flush();
+}
+
+/// The commands to run for a configured command, with each argument translated through the
+/// config: the `execute_before` of the command and its subcommands, the command itself, then their
+/// `execute_after`.
+fn translate_command(arg_parser: &parser::ArgumentParser, args: &[String]) -> Vec<String> {
+    let mut command_strs = vec![];
+    let mut iter = parser::ArgumentIterator::new(arg_parser);
+    iter.reinit(args.to_vec());
+    // The executable is replaced by `exe_to`, so starts from the argument after it
+    let mut i = 1;
+    // The command, followed by the subcommands chosen
+    let mut chain = vec![arg_parser.cmd()];
+    let mut shell_str = String::new();
+    shell_str += &arg_parser.cmd().exe_to;
+    shell_str += " ";
+    let _ = iter.next();
+    for arg in iter {
+        match arg {
+            parser::Argument::Other | parser::Argument::Excess | parser::Argument::UnknownFlag => {
+                shell_str += &args[i];
+            }
+            parser::Argument::ArgFlag(arg_flag) => {
+                let arg_flag = unsafe { &*arg_flag };
+                shell_str += &arg_flag.flag_to;
+                i += 1;
+                if let Some(arg) = args.get(i) {
+                    shell_str += " ";
+                    shell_str += arg;
+                }
+            }
+            parser::Argument::Flag(flag) => {
+                let flag = unsafe { &*flag };
+                shell_str += &flag.flag_to;
+            }
+            parser::Argument::Flags(group) => {
+                // Each flag is passed on by itself, with any value as the next argument
+                let mut parts = group.flags.iter()
+                    .map(|flag| unsafe { &**flag }.flag_to.clone())
+                    .collect::<Vec<_>>();
+                if let Some((arg_flag, value_start)) = group.arg_flag {
+                    parts.push(unsafe { &*arg_flag }.flag_to.clone());
+                    let value = match value_start {
+                        Some(value_start) => args[i].get(value_start..),
+                        None => {
+                            i += 1;
+                            args.get(i).map(String::as_str)
+                        }
+                    };
+                    parts.extend(value.filter(|value| !value.is_empty()).map(str::to_string));
+                }
+                shell_str += &parts.join(" ");
+            }
+            parser::Argument::EndOfFlags => shell_str += "--",
+            parser::Argument::Subcommand(parent) => {
+                let parent = unsafe { &*parent };
+                match parent.subcommand(&args[i]) {
+                    Some(subcommand) => {
+                        shell_str += &subcommand.exe_to;
+                        chain.push(subcommand);
+                    }
+                    None => shell_str += &args[i],
+                }
+            }
+            parser::Argument::Arg(_arg) => { shell_str += &args[i]; }
+        }
+        shell_str += " ";
+        i += 1;
+    }
+    shell_str.pop();
+
+    for cmd in chain.iter().filter_map(|cmd| cmd.execute_before.as_ref()) {
+        command_strs.push(parse_execution_cmd(args, cmd));
+    }
+    command_strs.push(shell_str);
+    for cmd in chain.iter().rev().filter_map(|cmd| cmd.execute_after.as_ref()) {
+        command_strs.push(parse_execution_cmd(args, cmd));
+    }
+    command_strs
+}
+
 pub fn run_command(
     program_state: Rc<RefCell<state::ProgramState>>,
     buffer: &buffer::InputBuffer,
     arg_parser: &parser::ArgumentParser,
-) -> (Option<NewTerminalLine>, Option<super::StatusCode>) {
+) -> (Option<NewTerminalLine>, Option<super::StatusCode>, Option<super::CommandTiming>) {
     match reserved_commands(
         program_state.clone(),
         buffer,
@@ -69,11 +264,12 @@ pub fn run_command(
         Some(action) => match action {
             super::ReservedFuncReturn::Ok => (),
             super::ReservedFuncReturn::Status(_) => (),
-            super::ReservedFuncReturn::DontExecute(_) => return (None, None)
+            super::ReservedFuncReturn::DontExecute(status) => return (None, Some(status), None),
+            super::ReservedFuncReturn::Printed(line, status) => return (Some(line), Some(status), None),
         }
     };
 
-    if buffer.len() == 0 { return (None, Some(-1)); }
+    if buffer.len() == 0 { return (None, Some(-1), None); }
 
     let p_state = program_state.borrow();
 
@@ -82,7 +278,6 @@ pub fn run_command(
             .map(|range| buffer.get_buffer_str(range))
             .collect::<Vec<_>>();
 
-        let mut command_strs = vec![];
         if !arg_parser.has_command() {
             let mut shell_str = String::with_capacity(buffer.len());
             for split in buffer.arg_locs_iterator() {
@@ -90,73 +285,44 @@ pub fn run_command(
                 shell_str += " ";
             }
             shell_str.pop();
-            command_strs.push(shell_str);
+            vec![shell_str]
         } else {
-            if let Some(cmd) = &arg_parser.cmd().execute_before {
-                command_strs.push(parse_execution_cmd(&args, cmd));
-            }
-
-            let mut iter = parser::ArgumentIterator::new(&arg_parser);
-            iter.reinit(args.clone());
-            let mut i = 0;
-            let mut shell_str = String::with_capacity(buffer.len());
-            shell_str += &arg_parser.cmd().exe_to;
-            shell_str += " ";
-            let _ = iter.next();
-            for arg in iter {
-                match arg {
-                    parser::Argument::Other => { shell_str += &args[i]; }
-                    parser::Argument::ArgFlag(arg_flag) => {
-                        let arg_flag = unsafe { &*arg_flag };
-                        shell_str += &arg_flag.flag_to;
-                        i += 1;
-                        shell_str += " ";
-                        shell_str += &args[i];
-                    }
-                    parser::Argument::Flag(flag) => {
-                        let flag = unsafe { &*flag };
-                        shell_str += &flag.flag_to;
-                    }
-                    parser::Argument::Arg(arg) => { shell_str += &args[i]; }
-                }
-                shell_str += " ";
-                i += 1;
-            }
-            shell_str.pop();
-            command_strs.push(shell_str);
-
-            if let Some(cmd) = &arg_parser.cmd().execute_after {
-                command_strs.push(parse_execution_cmd(&args, cmd));
-            }
+            translate_command(arg_parser, &args)
         }
-        command_strs
     };
 
     { // Run the commands
-        let run_cmd = |exec_str: &str| -> std::process::ExitStatus {
+        let run_cmd = |exec_str: &str, timing: &mut super::CommandTiming| {
             let mut command = p_state.current_shell.to_exec();
             command.arg(exec_str);
-            let mut child = command.spawn().unwrap();
-            let exit = child.wait().unwrap();
-            exit
+            let start = time::Instant::now();
+            let (status, cpu, pid) = spawn_and_wait(command);
+            *timing += super::CommandTiming { wall: start.elapsed(), cpu };
+            (status, pid)
         };
 
-        println!();
-        ansi::move_to_column(0);
-        ansi::erase_screen_from_cursor();
-        ansi::flush();
+        begin_output();
 
-        crossterm::terminal::disable_raw_mode().unwrap();
-
-        let mut exit = run_cmd(&command_strs[0]);
-        for cmd in &command_strs[1..] {
-            exit = run_cmd(cmd);
+        let mut timing = super::CommandTiming::default();
+        let mut status = super::ChildStatus::Exited(0);
+        for cmd in &command_strs {
+            let pid;
+            (status, pid) = run_cmd(cmd, &mut timing);
+            if let super::ChildStatus::Exited(_) = status {
+                continue;
+            }
+            // Interrupted or stopped, so whatever was queued to run after it shouldn't run.
+            drop(p_state);
+            report_status(&program_state, cmd, pid, status);
+            break;
         }
 
-        crossterm::terminal::enable_raw_mode().unwrap();
+        // Named as typed, rather than after translation or by a hook run before it
+        let typed = buffer.get_buffer().iter().collect::<String>();
+        notify_if_slow(&program_state.borrow().config.execution, &typed, &timing);
 
-        let new_pos = ansi::cursor_pos().unwrap();
+        let new_line = end_output();
 
-        return (Some(new_pos.1), exit.code());
+        (Some(new_line), status.code(), Some(timing))
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,132 @@
+//! Job control for the commands whale runs.
+//! Each command is started in its own process group which is handed the terminal while it runs, so
+//! `Ctrl+C`, `Ctrl+\` and `Ctrl+Z` reach the command instead of whale. Whale takes the terminal back
+//! once the command exits or is stopped.
+
+use std::os::unix::process::CommandExt;
+use std::process;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::time;
+
+use super::ChildStatus;
+
+/// Process group of the command currently in the foreground, 0 if there isn't one.
+static FOREGROUND_PGID: AtomicI32 = AtomicI32::new(0);
+
+const JOB_CONTROL_SIGNALS: &[libc::c_int] = &[
+    libc::SIGINT,
+    libc::SIGQUIT,
+    libc::SIGTSTP,
+    libc::SIGTTIN,
+    libc::SIGTTOU,
+];
+
+fn forward_signal(signal: libc::c_int) {
+    let pgid = FOREGROUND_PGID.load(Ordering::SeqCst);
+    if pgid > 0 {
+        unsafe { libc::kill(-pgid, signal) };
+    }
+}
+
+/// Should be called once at startup, before any commands are run.
+pub fn install_handlers() {
+    unsafe {
+        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+        libc::signal(libc::SIGTSTP, libc::SIG_IGN);
+        libc::signal(libc::SIGTTIN, libc::SIG_IGN);
+        libc::signal(libc::SIGTTOU, libc::SIG_IGN);
+    }
+    // The terminal delivers SIGINT to the foreground command directly, this covers the cases where
+    // whale receives it anyway (e.g. when stdin is not a terminal).
+    let _ = ctrlc::set_handler(|| forward_signal(libc::SIGINT));
+}
+
+fn give_terminal_to(pgid: libc::pid_t) {
+    unsafe {
+        if libc::isatty(libc::STDIN_FILENO) == 1 {
+            libc::tcsetpgrp(libc::STDIN_FILENO, pgid);
+        }
+    }
+}
+
+/// Puts the command in a process group of its own and restores the default signal dispositions
+/// that whale overrode in `install_handlers`.
+pub fn prepare_child(command: &mut process::Command) {
+    command.process_group(0);
+    unsafe {
+        command.pre_exec(|| {
+            // Done in both the parent and the child, so that whichever runs first wins the race.
+            give_terminal_to(libc::getpid());
+            for signal in JOB_CONTROL_SIGNALS {
+                libc::signal(*signal, libc::SIG_DFL);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Waits for the process group led by `pid` to exit or stop, returning its CPU time alongside.
+pub fn wait_foreground(pid: u32) -> (ChildStatus, time::Duration) {
+    let pid = pid as libc::pid_t;
+    FOREGROUND_PGID.store(pid, Ordering::SeqCst);
+    give_terminal_to(pid);
+
+    let mut status = 0;
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    let waited = loop {
+        let waited = unsafe { libc::wait4(pid, &mut status, libc::WUNTRACED, &mut usage) };
+        if waited >= 0 || std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+            break waited;
+        }
+    };
+
+    FOREGROUND_PGID.store(0, Ordering::SeqCst);
+    give_terminal_to(unsafe { libc::getpgrp() });
+
+    let to_duration = |t: libc::timeval| {
+        time::Duration::from_secs(t.tv_sec as u64) + time::Duration::from_micros(t.tv_usec as u64)
+    };
+    let cpu = to_duration(usage.ru_utime) + to_duration(usage.ru_stime);
+
+    let child_status = if waited < 0 {
+        ChildStatus::Exited(-1)
+    } else if libc::WIFSTOPPED(status) {
+        ChildStatus::Stopped(libc::WSTOPSIG(status))
+    } else if libc::WIFSIGNALED(status) {
+        ChildStatus::Signaled(libc::WTERMSIG(status))
+    } else {
+        ChildStatus::Exited(libc::WEXITSTATUS(status))
+    };
+    (child_status, cpu)
+}
+
+/// Continues a stopped job in the foreground.
+pub fn resume(pid: u32) -> (ChildStatus, time::Duration) {
+    give_terminal_to(pid as libc::pid_t);
+    unsafe { libc::kill(-(pid as libc::pid_t), libc::SIGCONT) };
+    wait_foreground(pid)
+}
+
+pub fn signal_name(signal: i32) -> String {
+    let name = match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGSTOP => "SIGSTOP",
+        libc::SIGTSTP => "SIGTSTP",
+        libc::SIGTTIN => "SIGTTIN",
+        libc::SIGTTOU => "SIGTTOU",
+        _ => return format!("signal {}", signal),
+    };
+    name.to_string()
+}
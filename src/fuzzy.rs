@@ -0,0 +1,187 @@
+//! String matching shared by the history search and the hints.
+//! Positions are returned as `char` indices into the candidate so that matched characters can be
+//! highlighted.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum MatchMode {
+    /// The candidate must start with the pattern.
+    Prefix,
+    /// The pattern must appear somewhere in the candidate.
+    Substring,
+    /// The characters of the pattern must appear in order, but not necessarily next to each other.
+    #[default]
+    Fuzzy,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Match {
+    pub score: i64,
+    pub positions: Vec<usize>,
+}
+
+const SCORE_MATCH: i64 = 16;
+const BONUS_BOUNDARY: i64 = 8;
+const BONUS_CONSECUTIVE: i64 = 6;
+const BONUS_CASE: i64 = 1;
+const BONUS_FIRST_CHAR: i64 = 4;
+const PENALTY_GAP: i64 = 1;
+
+fn is_boundary(prev: Option<char>, curr: char) -> bool {
+    match prev {
+        None => true,
+        Some(p) => {
+            matches!(p, ' ' | '/' | '\\' | '-' | '_' | '.' | ':' | '=' | '"' | '\'')
+                || (p.is_lowercase() && curr.is_uppercase())
+        }
+    }
+}
+
+/// Matching is case insensitive unless the pattern contains an uppercase character.
+fn chars_eq(pattern: char, candidate: char, case_sensitive: bool) -> bool {
+    match case_sensitive {
+        true => pattern == candidate,
+        false => pattern.to_lowercase().eq(candidate.to_lowercase()),
+    }
+}
+
+fn score_positions(pattern: &[char], candidate: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0;
+    let mut prev_pos: Option<usize> = None;
+    for (p, pos) in pattern.iter().zip(positions) {
+        let c = candidate[*pos];
+        score += SCORE_MATCH;
+        if *p == c {
+            score += BONUS_CASE;
+        }
+        let prev_char = pos.checked_sub(1).map(|i| candidate[i]);
+        if is_boundary(prev_char, c) {
+            score += BONUS_BOUNDARY;
+        }
+        match prev_pos {
+            Some(prev) if prev + 1 == *pos => score += BONUS_CONSECUTIVE,
+            Some(prev) => score -= PENALTY_GAP * (pos - prev - 1) as i64,
+            None => {
+                if *pos == 0 {
+                    score += BONUS_FIRST_CHAR;
+                }
+            }
+        }
+        prev_pos = Some(*pos);
+    }
+    // Prefer shorter candidates when everything else is equal
+    score - (candidate.len() as i64 / 8)
+}
+
+/// Greedily matches `pattern` from every position the first character appears at, keeping the
+/// best scoring alignment.
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<Match> {
+    let pattern = pattern.chars().collect::<Vec<char>>();
+    let candidate = candidate.chars().collect::<Vec<char>>();
+    if pattern.is_empty() {
+        return Some(Match { score: 0, positions: vec![] });
+    }
+    let case_sensitive = pattern.iter().any(|c| c.is_uppercase());
+
+    let mut best: Option<Match> = None;
+    for start in 0..candidate.len() {
+        if !chars_eq(pattern[0], candidate[start], case_sensitive) {
+            continue;
+        }
+        let mut positions = Vec::with_capacity(pattern.len());
+        positions.push(start);
+        let mut c = start + 1;
+        for p in &pattern[1..] {
+            while c < candidate.len() && !chars_eq(*p, candidate[c], case_sensitive) {
+                c += 1;
+            }
+            if c == candidate.len() {
+                break;
+            }
+            positions.push(c);
+            c += 1;
+        }
+        if positions.len() != pattern.len() {
+            // Later starting points can only find fewer characters
+            break;
+        }
+        let score = score_positions(&pattern, &candidate, &positions);
+        if best.as_ref().is_none_or(|b| score > b.score) {
+            best = Some(Match { score, positions });
+        }
+    }
+    best
+}
+
+pub fn substring_match(pattern: &str, candidate: &str) -> Option<Match> {
+    let pattern_chars = pattern.chars().collect::<Vec<char>>();
+    let candidate_chars = candidate.chars().collect::<Vec<char>>();
+    let case_sensitive = pattern_chars.iter().any(|c| c.is_uppercase());
+    if pattern_chars.len() > candidate_chars.len() {
+        return None;
+    }
+
+    (0..=candidate_chars.len() - pattern_chars.len())
+        .filter(|start| {
+            pattern_chars
+                .iter()
+                .zip(&candidate_chars[*start..])
+                .all(|(p, c)| chars_eq(*p, *c, case_sensitive))
+        })
+        .map(|start| {
+            let positions = (start..start + pattern_chars.len()).collect::<Vec<usize>>();
+            let score = score_positions(&pattern_chars, &candidate_chars, &positions);
+            Match { score, positions }
+        })
+        .max_by_key(|m| m.score)
+}
+
+pub fn prefix_match(pattern: &str, candidate: &str) -> Option<Match> {
+    if !candidate.starts_with(pattern) {
+        return None;
+    }
+    let positions = (0..pattern.chars().count()).collect::<Vec<usize>>();
+    // All prefix matches score the same, so the order of the candidates is kept
+    Some(Match { score: 0, positions })
+}
+
+pub fn find(mode: MatchMode, pattern: &str, candidate: &str) -> Option<Match> {
+    match mode {
+        MatchMode::Prefix => prefix_match(pattern, candidate),
+        MatchMode::Substring => substring_match(pattern, candidate),
+        MatchMode::Fuzzy => fuzzy_match(pattern, candidate),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match_positions() {
+        let m = fuzzy_match("gco", "git checkout").unwrap();
+        assert_eq!(m.positions, vec![0, 4, 9]);
+        assert!(fuzzy_match("gx", "git checkout").is_none());
+        assert!(fuzzy_match("", "anything").unwrap().positions.is_empty());
+    }
+
+    #[test]
+    fn test_fuzzy_match_prefers_boundaries_and_runs() {
+        let boundary = fuzzy_match("cb", "cargo build").unwrap();
+        let middle = fuzzy_match("cb", "scrabble").unwrap();
+        assert!(boundary.score > middle.score);
+
+        let contiguous = fuzzy_match("stat", "git status").unwrap();
+        let scattered = fuzzy_match("stat", "set tag at").unwrap();
+        assert!(contiguous.score > scattered.score);
+    }
+
+    #[test]
+    fn test_smart_case() {
+        assert!(fuzzy_match("readme", "README.md").is_some());
+        assert!(fuzzy_match("README", "readme.md").is_none());
+        assert!(substring_match("Make", "cmake").is_none());
+        assert_eq!(substring_match("make", "cmake").unwrap().positions, vec![1, 2, 3, 4]);
+    }
+}
@@ -1,6 +1,6 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use crate::{ansi, buffer, state};
+use crate::{ansi, buffer, fuzzy, state};
 use crate::ansi::TerminalXY;
 use crate::gui::{ActionToExecute, ActionToTake, ActionType, HighlightDrawn};
 use crate::gui::terminal::CursorPos;
@@ -14,6 +14,7 @@ pub struct DropdownGUI {
 
     // Temporary variables used to transition between functions
     prev_len: usize,
+    /// Indices into the hint's ranked matches.
     hints_iterator: Vec<usize>,
 
     arg_start: CursorPos,
@@ -83,26 +84,18 @@ impl super::GUITrait for DropdownGUI {
             .min(self.program_state.borrow().config.gui.dropdown.max_rows);
 
         // Find relevant hints
-        // TODO: This is a bit of a mess
         let mut disregard = 0;
-        let mut arg = String::new();
         let mut hint: &[String] = &[];
+        let mut ranked: &[(usize, fuzzy::Match)] = &[];
         if let Some(h) = buffer.get_curr_hint_safe() {
-            arg = h.0;
             hint = h.1.get_selection();
+            ranked = h.1.ranked();
             disregard = h.1.disregard();
         };
 
         self.arg_start = (arg_pos.0 + disregard as u16, arg_pos.1);
 
-        let mut hint_indexes = Vec::with_capacity(dropdown_rows as usize);
-        for (i, s) in hint.iter().enumerate() {
-            if s.starts_with(&arg[disregard..]) {
-                hint_indexes.push(i);
-            }
-        }
-
-        let num_hints = hint_indexes.len();
+        let num_hints = ranked.len();
 
         // Reset dropdown if hints have changed
         if self.prev_len != num_hints {
@@ -125,16 +118,14 @@ impl super::GUITrait for DropdownGUI {
         self.hints_iterator = {
             let upper = (cur_max + 1).min(num_hints);
             (0..upper)
-                .map(|x| {
-                    hint_indexes[(x + self.table_scroll).rem_euclid(num_hints)]
-                }).collect()
+                .map(|x| (x + self.table_scroll).rem_euclid(num_hints))
+                .collect()
         };
         let ind = self.cursor_pos + self.table_scroll;
 
         // Return action
         if should_set_closest {
-            let hint_ind = hint_indexes[ind];
-            let hint = hint[hint_ind].clone();
+            let hint = hint[ranked[ind].0].clone();
             ActionToTake::WriteBuffer(ActionType::Other(ActionToExecute::SetClosestMatch(hint)))
         } else {
             ActionToTake::WriteBuffer(ActionType::Standard)
@@ -153,47 +144,52 @@ impl super::GUITrait for DropdownGUI {
         ansi::erase_line();
 
         let program_state = self.program_state.borrow();
-        fn multi_line_str(s: &str, max_len: usize) -> Vec<&str> {
-            let mut lines = vec![];
-            let max_ind = max_len.min(s.len());
-            lines.push(&s[..max_ind]);
-            if s.len() >= max_len {
-                lines.extend(multi_line_str(&s[..max_len], max_len));
-            }
-            lines
-        }
+        let theme = &program_state.config.theme;
 
         {
             let mut hints: &[String] = &[];
-            if let Some(hint) = buf.get_curr_hint_safe() {
+            let mut ranked: &[(usize, fuzzy::Match)] = &[];
+            let curr_hint = buf.get_curr_hint_safe();
+            if let Some(hint) = &curr_hint {
                 hints = hint.1.get_selection();
+                ranked = hint.1.ranked();
             }
 
             let mut cursor_drawn = HighlightDrawn::Before;
-            let mut style = &program_state.config.theme.console_secondary.normal;
-            let max_len = (term_size.0 - self.arg_start.0) as usize;
+            let mut style = (&theme.console_secondary.normal, &theme.console_main.normal);
+            let max_len = ((term_size.0 - self.arg_start.0) as usize).max(1);
             let max_lines = (term_size.1 - self.arg_start.1) as usize;
             let mut num_lines = 1;
             for (i, ind) in self.hints_iterator.iter().enumerate() {
-                let item = &hints[*ind];
+                let (item_ind, m) = &ranked[*ind];
+                let item = hints[*item_ind].chars().collect::<Vec<char>>();
 
                 if cursor_drawn == HighlightDrawn::Before && i == self.cursor_pos {
                     cursor_drawn = HighlightDrawn::During;
-                    style = &program_state.config.theme.console_secondary.highlighted;
+                    style = (&theme.console_secondary.highlighted, &theme.console_main.highlighted);
                 } else if cursor_drawn == HighlightDrawn::During {
                     cursor_drawn = HighlightDrawn::After;
-                    style = &program_state.config.theme.console_secondary.normal;
+                    style = (&theme.console_secondary.normal, &theme.console_main.normal);
                 };
 
-                let lines = multi_line_str(&item, max_len);
-                for l in lines.into_iter() {
+                // Items too long for the terminal are wrapped onto the following lines
+                let first_line = num_lines;
+                for (line, chunk) in item.chunks(max_len).enumerate() {
                     if num_lines >= max_lines {
                         break;
                     }
                     ansi::move_to((self.arg_start.0, self.arg_start.1 + num_lines as u16));
-                    super::output_str(style, format!("{}", l).as_str());
+                    let l = chunk.iter().collect::<String>();
+                    super::output_matched(style.0, style.1, &l, &m.positions, line * max_len);
                     num_lines += 1;
                 }
+
+                // Described items are followed by their description, if there is room for it
+                let description = curr_hint.as_ref().and_then(|hint| hint.1.description(*item_ind));
+                if let Some(description) = description.filter(|_| num_lines > first_line && item.len() + 2 < max_len) {
+                    let description = description.chars().take(max_len - item.len() - 2).collect::<String>();
+                    super::output_str(style.0, &format!("  {}", description));
+                }
             }
         }
     }
@@ -0,0 +1,209 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::{ansi, buffer, fuzzy, state, utils};
+use crate::ansi::TerminalXY;
+use crate::gui::{ActionToExecute, ActionToTake, ActionType, ViewType};
+use crate::gui::terminal::CursorPos;
+use crate::history::{data_layer, ux_layer, HistoryScope};
+use crate::input::InputEvent;
+
+/// Reverse search through the history, opened with `Ctrl+R`.
+/// Whatever is typed into the buffer is the query. The search itself runs in the history data
+/// layer, this view only ever holds the matches that are on screen.
+/// `Ctrl+S` switches between fuzzy and substring matching, `Ctrl+G` between the history scopes,
+/// `Enter` puts the selected command into the buffer and `Esc` closes the view.
+pub struct HistorySearchGUI {
+    program_state: Rc<RefCell<state::ProgramState>>,
+    history: ux_layer::History,
+
+    query: Option<String>,
+    mode: fuzzy::MatchMode,
+    /// `Ctrl+G` is handled by `main`, changing the scope in the program state.
+    scope: HistoryScope,
+
+    selected: usize,
+    scroll: usize,
+
+    // Temporary variables used to transition between functions
+    num_rows: usize,
+    total: usize,
+    matches: Vec<data_layer::SearchMatch>,
+}
+
+impl HistorySearchGUI {
+    pub fn init(program_state: Rc<RefCell<state::ProgramState>>) -> Self {
+        let history = ux_layer::History::init(program_state.clone());
+        let scope = history.scope();
+        Self {
+            program_state,
+            history,
+            query: None,
+            mode: fuzzy::MatchMode::Fuzzy,
+            scope,
+            selected: 0,
+            scroll: 0,
+            num_rows: 0,
+            total: 0,
+            matches: vec![],
+        }
+    }
+
+    fn mode_name(&self) -> &'static str {
+        match self.mode {
+            fuzzy::MatchMode::Prefix => "prefix",
+            fuzzy::MatchMode::Substring => "substring",
+            fuzzy::MatchMode::Fuzzy => "fuzzy",
+        }
+    }
+
+    fn fetch(&mut self) {
+        let query = self.query.clone().unwrap_or_default();
+        let scope = self.scope.clone();
+        let (total, matches) = self.history.search(&query, self.mode, scope, self.scroll, self.num_rows);
+        self.total = total;
+        self.matches = matches;
+    }
+
+    #[inline(always)]
+    fn arrow_up(&mut self) {
+        if self.selected > 0 {
+            self.selected -= 1;
+            if self.selected < self.scroll {
+                self.scroll = self.selected;
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn arrow_down(&mut self) {
+        if self.selected + 1 < self.total {
+            self.selected += 1;
+            if self.selected >= self.scroll + self.num_rows {
+                self.scroll = self.selected + 1 - self.num_rows;
+            }
+        }
+    }
+}
+
+impl super::GUITrait for HistorySearchGUI {
+    fn view_type(&self) -> ViewType {
+        ViewType::HistorySearch
+    }
+
+    #[allow(unused_variables)]
+    fn action_before_write(
+        &mut self,
+        event: InputEvent,
+        buffer: &buffer::InputBuffer,
+        term_size: TerminalXY,
+        write_from_line: u16,
+        cursor_pos: CursorPos,
+        arg_pos: CursorPos,
+    ) -> ActionToTake {
+        // One line is used for the status
+        let num_rows = term_size.1.saturating_sub(write_from_line + 1).max(1) as usize;
+        let query = buffer.get_buffer().iter().collect::<String>();
+        let mut refetch = num_rows != self.num_rows;
+        self.num_rows = num_rows;
+        let scope = self.history.scope();
+        if self.query.as_ref() != Some(&query) || self.scope != scope {
+            self.query = Some(query);
+            self.scope = scope;
+            self.selected = 0;
+            self.scroll = 0;
+            refetch = true;
+        }
+
+        let scroll_before = self.scroll;
+        let action = match event {
+            InputEvent::ArrowUp => {
+                self.arrow_up();
+                ActionToTake::BlockBuffer
+            }
+            InputEvent::ArrowDown => {
+                self.arrow_down();
+                ActionToTake::BlockBuffer
+            }
+            InputEvent::CtrlS => {
+                self.mode = match self.mode {
+                    fuzzy::MatchMode::Fuzzy => fuzzy::MatchMode::Substring,
+                    _ => fuzzy::MatchMode::Fuzzy,
+                };
+                self.selected = 0;
+                self.scroll = 0;
+                refetch = true;
+                ActionToTake::BlockBuffer
+            }
+            InputEvent::Enter => {
+                let action = match self.matches.get(self.selected - self.scroll) {
+                    Some(m) => ActionToExecute::SetBuffer(m.entry.command().to_string()),
+                    None => ActionToExecute::CloseView,
+                };
+                return ActionToTake::WriteBuffer(ActionType::Other(action));
+            }
+            InputEvent::Esc => ActionToTake::WriteBuffer(ActionType::Other(ActionToExecute::CloseView)),
+            _ => ActionToTake::WriteBuffer(ActionType::Standard),
+        };
+
+        if refetch || scroll_before != self.scroll {
+            self.fetch();
+        }
+        action
+    }
+
+    #[allow(unused_variables)]
+    fn write_output(
+        &mut self,
+        event: InputEvent,
+        term_size: TerminalXY,
+        write_from_line: u16,
+        buf: &buffer::InputBuffer,
+    ) {
+        ansi::move_to((0, write_from_line));
+        ansi::erase_screen_from_cursor();
+
+        let program_state = self.program_state.borrow();
+        let theme = &program_state.config.theme;
+
+        {
+            let s = format!(
+                "history search ({}, {}), {} matches",
+                self.mode_name(),
+                program_state.history_scope.name(),
+                self.total
+            );
+            super::output_str(&theme.console_main.normal, s.as_str());
+        }
+
+        for (row, m) in self.matches.iter().enumerate() {
+            ansi::move_down(1);
+            ansi::move_to_column(0);
+
+            let selected = self.scroll + row == self.selected;
+            let (marker, style) = match selected {
+                true => ("> ", &theme.console_main),
+                false => ("  ", &theme.console_secondary),
+            };
+            let timestamp = utils::format_timestamp(m.entry.timestamp());
+            super::output_str(&style.normal, format!("{}{}  ", marker, timestamp).as_str());
+
+            let max_len = (term_size.0 as usize).saturating_sub(timestamp.chars().count() + 4);
+            let (add_dots, command) = super::shorten_str(m.entry.command(), max_len);
+            for (i, c) in command.chars().enumerate() {
+                let style = match m.positions.contains(&i) {
+                    true => &style.highlighted,
+                    false => &style.normal,
+                };
+                super::output_str(style, c.to_string().as_str());
+            }
+            if add_dots {
+                super::output_str(&style.normal, "...");
+            }
+        }
+    }
+
+    fn clear_output(&mut self, write_from_line: u16) {
+        ansi::move_to((0, write_from_line));
+        ansi::erase_screen_from_cursor();
+    }
+}
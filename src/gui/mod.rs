@@ -7,6 +7,8 @@ pub mod table;
 pub mod dropdown;
 pub mod terminal;
 pub mod explorer;
+pub mod records;
+pub mod history_search;
 
 #[derive(PartialEq)]
 enum HighlightDrawn {
@@ -34,6 +36,7 @@ pub enum ActionType {
 pub enum ActionToExecute {
     SetBuffer(String),
     SetClosestMatch(String),
+    CloseView,
 }
 
 #[derive(Debug, PartialEq)]
@@ -47,6 +50,8 @@ pub enum ViewType {
     Table,
     Dropdown,
     Explorer,
+    Records,
+    HistorySearch,
 }
 
 pub enum ViewTypeData {
@@ -54,6 +59,8 @@ pub enum ViewTypeData {
     Table(table::TableGUI),
     Dropdown(dropdown::DropdownGUI),
     Explorer(explorer::FileExplorerGUI),
+    Records(records::RecordsGUI),
+    HistorySearch(Box<history_search::HistorySearchGUI>),
 }
 
 pub trait GUITrait {
@@ -81,3 +88,38 @@ pub fn output_str(style: &theme::Style, s: &str) {
     print!("{}{}", style.escape_sequence, s);
     ansi::reset();
 }
+
+/// Writes `s` with the characters at `positions` in `match_style`. `positions` are `char` indices
+/// into the string `s` was cut from, and `offset` is where `s` starts in it.
+pub fn output_matched(style: &theme::Style, match_style: &theme::Style, s: &str, positions: &[usize], offset: usize) {
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in s.chars().enumerate() {
+        let matched = positions.contains(&(offset + i));
+        if matched != run_matched && !run.is_empty() {
+            output_str(if run_matched { match_style } else { style }, &run);
+            run.clear();
+        }
+        run_matched = matched;
+        run.push(c);
+    }
+    if !run.is_empty() {
+        output_str(if run_matched { match_style } else { style }, &run);
+    }
+}
+
+pub type AddDots = bool;
+
+/// Cuts `s` down so that it, plus a trailing `...`, fits within `max_len`.
+pub fn shorten_str(s: &str, max_len: usize) -> (AddDots, &str) {
+    // shorten with ...
+    if s.chars().count() > max_len {
+        let end = s
+            .char_indices()
+            .nth(max_len.saturating_sub(3))
+            .map_or(s.len(), |(i, _)| i);
+        (true, &s[..end])
+    } else {
+        (false, s)
+    }
+}
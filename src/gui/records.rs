@@ -0,0 +1,312 @@
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::rc::Rc;
+use crate::{ansi, buffer, state, tabular};
+use crate::ansi::TerminalXY;
+use crate::gui::{ActionToExecute, ActionToTake, ActionType, ViewType};
+use crate::gui::terminal::CursorPos;
+use crate::input::InputEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortOrder {
+    None,
+    Ascending,
+    Descending,
+}
+
+impl SortOrder {
+    fn next(self) -> Self {
+        match self {
+            Self::None => Self::Ascending,
+            Self::Ascending => Self::Descending,
+            Self::Descending => Self::None,
+        }
+    }
+
+    fn symbol(self) -> &'static str {
+        match self {
+            Self::None => "",
+            Self::Ascending => " ^",
+            Self::Descending => " v",
+        }
+    }
+}
+
+/// Narrower cells couldn't fit the `...` of a shortened field, and a width of 0 has no columns.
+const MIN_FIELD_LEN: u16 = 4;
+
+/// Numbers are compared as numbers so that e.g. sizes and PIDs sort the way you would expect.
+fn compare_cells(a: &str, b: &str) -> Ordering {
+    match (a.trim().parse::<f64>(), b.trim().parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+        _ => a.cmp(b),
+    }
+}
+
+/// Displays a `tabular::Table` produced by the `table` command.
+/// Arrow keys move between cells, `Ctrl+S` cycles the sort order of the selected column, `Enter`
+/// or `Esc` closes the view, and whatever is typed into the buffer filters the rows. The filter is
+/// cleared on closing, so it is never run as a command.
+pub struct RecordsGUI {
+    program_state: Rc<RefCell<state::ProgramState>>,
+
+    table: tabular::Table,
+    sort_col: usize,
+    sort_order: SortOrder,
+
+    cursor_pos: TerminalXY,
+    row_scroll: usize,
+    col_scroll: usize,
+
+    // Temporary variables used to transition between functions
+    filter: String,
+    grid_slots: TerminalXY,
+    rows_iterator: Vec<usize>,
+}
+
+impl RecordsGUI {
+    pub fn init(program_state: Rc<RefCell<state::ProgramState>>) -> Self {
+        let table = program_state
+            .borrow_mut()
+            .pending_table
+            .take()
+            .unwrap_or(tabular::Table { headers: vec![], rows: vec![] });
+        Self {
+            program_state,
+            table,
+            sort_col: 0,
+            sort_order: SortOrder::None,
+            cursor_pos: (0, 0),
+            row_scroll: 0,
+            col_scroll: 0,
+            filter: String::new(),
+            grid_slots: (0, 0),
+            rows_iterator: Vec::new(),
+        }
+    }
+
+    fn matching_rows(&self) -> Vec<usize> {
+        let filter = self.filter.to_lowercase();
+        let mut rows = (0..self.table.num_rows())
+            .filter(|i| {
+                filter.is_empty()
+                    || self.table.rows[*i]
+                    .iter()
+                    .any(|cell| cell.to_lowercase().contains(&filter))
+            })
+            .collect::<Vec<usize>>();
+
+        let col = self.sort_col;
+        match self.sort_order {
+            SortOrder::None => (),
+            SortOrder::Ascending => {
+                rows.sort_by(|a, b| compare_cells(&self.table.rows[*a][col], &self.table.rows[*b][col]))
+            }
+            SortOrder::Descending => {
+                rows.sort_by(|a, b| compare_cells(&self.table.rows[*b][col], &self.table.rows[*a][col]))
+            }
+        }
+        rows
+    }
+
+    fn field_len(&self) -> u16 {
+        self.program_state.borrow().config.gui.table.max_field_len.max(MIN_FIELD_LEN)
+    }
+
+    #[inline(always)]
+    fn arrow_up(&mut self, num_rows: usize) {
+        if self.cursor_pos.1 > 0 {
+            self.cursor_pos.1 -= 1;
+        } else if self.row_scroll > 0 {
+            self.row_scroll -= 1;
+        } else if num_rows > 0 {
+            let visible = (self.grid_slots.1 as usize).min(num_rows);
+            self.cursor_pos.1 = visible.saturating_sub(1) as u16;
+            self.row_scroll = num_rows - visible;
+        }
+    }
+
+    #[inline(always)]
+    fn arrow_down(&mut self, num_rows: usize) {
+        let ind = self.row_scroll + self.cursor_pos.1 as usize;
+        if ind + 1 >= num_rows {
+            self.cursor_pos.1 = 0;
+            self.row_scroll = 0;
+        } else if (self.cursor_pos.1 as usize) + 1 < self.grid_slots.1 as usize {
+            self.cursor_pos.1 += 1;
+        } else {
+            self.row_scroll += 1;
+        }
+    }
+
+    #[inline(always)]
+    fn arrow_left(&mut self) {
+        if self.cursor_pos.0 > 0 {
+            self.cursor_pos.0 -= 1;
+        } else if self.col_scroll > 0 {
+            self.col_scroll -= 1;
+        }
+    }
+
+    #[inline(always)]
+    fn arrow_right(&mut self) {
+        let col = self.col_scroll + self.cursor_pos.0 as usize;
+        if col + 1 >= self.table.num_cols() {
+            return;
+        }
+        if (self.cursor_pos.0 as usize) + 1 < self.grid_slots.0 as usize {
+            self.cursor_pos.0 += 1;
+        } else {
+            self.col_scroll += 1;
+        }
+    }
+}
+
+impl super::GUITrait for RecordsGUI {
+    fn view_type(&self) -> ViewType {
+        ViewType::Records
+    }
+
+    #[allow(unused_variables)]
+    fn action_before_write(
+        &mut self,
+        event: InputEvent,
+        buffer: &buffer::InputBuffer,
+        term_size: TerminalXY,
+        write_from_line: u16,
+        cursor_pos: CursorPos,
+        arg_pos: CursorPos,
+    ) -> ActionToTake {
+        // Basic dimensions, one line for the status and one for the column headers
+        let write_table_from_line = (write_from_line + 2).min(term_size.1);
+        let max_field_len = self.field_len();
+        self.grid_slots = (
+            (term_size.0 / max_field_len).max(1),
+            term_size.1 - write_table_from_line
+        ) as TerminalXY;
+
+        let filter = buffer.get_buffer().iter().collect::<String>();
+        if filter != self.filter {
+            self.filter = filter;
+            self.cursor_pos.1 = 0;
+            self.row_scroll = 0;
+        }
+
+        let mut rows = self.matching_rows();
+
+        let action = match event {
+            InputEvent::ArrowUp => {
+                self.arrow_up(rows.len());
+                ActionToTake::BlockBuffer
+            }
+            InputEvent::ArrowDown => {
+                self.arrow_down(rows.len());
+                ActionToTake::BlockBuffer
+            }
+            InputEvent::ArrowLeft => {
+                self.arrow_left();
+                ActionToTake::BlockBuffer
+            }
+            InputEvent::ArrowRight => {
+                self.arrow_right();
+                ActionToTake::BlockBuffer
+            }
+            InputEvent::CtrlS => {
+                let col = self.col_scroll + self.cursor_pos.0 as usize;
+                if col != self.sort_col {
+                    self.sort_col = col;
+                    self.sort_order = SortOrder::None;
+                }
+                self.sort_order = self.sort_order.next();
+                rows = self.matching_rows();
+                ActionToTake::BlockBuffer
+            }
+            InputEvent::Enter | InputEvent::Esc => {
+                ActionToTake::WriteBuffer(ActionType::Other(ActionToExecute::SetBuffer(String::new())))
+            }
+            _ => ActionToTake::WriteBuffer(ActionType::Standard),
+        };
+
+        // Do some work for the next write_output stage
+        self.rows_iterator = rows
+            .into_iter()
+            .skip(self.row_scroll)
+            .take(self.grid_slots.1 as usize)
+            .collect();
+        action
+    }
+
+    #[allow(unused_variables)]
+    fn write_output(
+        &mut self,
+        event: InputEvent,
+        term_size: TerminalXY,
+        write_from_line: u16,
+        buf: &buffer::InputBuffer,
+    ) {
+        ansi::move_to((0, write_from_line));
+        ansi::erase_screen_from_cursor();
+
+        let program_state = self.program_state.borrow();
+        let theme = &program_state.config.theme;
+        let max_len = self.field_len() as usize;
+
+        fn output_cell(style: &crate::config::theme::Style, item: &str, max_len: usize) {
+            let l = match super::shorten_str(item, max_len - 1) {
+                (true, s) => {
+                    super::output_str(style, format!("{}...", s).as_str());
+                    s.chars().count() + 3
+                }
+                (false, s) => {
+                    super::output_str(style, s);
+                    s.chars().count()
+                }
+            };
+            for _ in 0..(max_len - l) {
+                print!(" ");
+            }
+        }
+
+        let cols = self.col_scroll
+            ..(self.col_scroll + self.grid_slots.0 as usize).min(self.table.num_cols());
+
+        {
+            let s = format!(
+                "{} rows, {} shown, cols {}-{} of {}",
+                self.table.num_rows(),
+                self.matching_rows().len(),
+                cols.start + 1,
+                cols.end,
+                self.table.num_cols()
+            );
+            super::output_str(&theme.console_main.normal, s.as_str());
+        }
+
+        ansi::move_down(1);
+        ansi::move_to_column(0);
+        for col in cols.clone() {
+            let mut header = self.table.headers[col].clone();
+            if col == self.sort_col {
+                header += self.sort_order.symbol();
+            }
+            output_cell(&theme.console_main.normal, &header, max_len);
+        }
+
+        for (row, i) in self.rows_iterator.iter().enumerate() {
+            ansi::move_down(1);
+            ansi::move_to_column(0);
+            for (c, col) in cols.clone().enumerate() {
+                let style = match row == self.cursor_pos.1 as usize && c == self.cursor_pos.0 as usize {
+                    true => &theme.console_secondary.highlighted,
+                    false => &theme.console_secondary.normal,
+                };
+                output_cell(style, &self.table.rows[*i][col], max_len);
+            }
+        }
+    }
+
+    fn clear_output(&mut self, write_from_line: u16) {
+        ansi::move_to((0, write_from_line));
+        ansi::erase_screen_from_cursor();
+    }
+}
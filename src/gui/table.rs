@@ -1,6 +1,6 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use crate::{ansi, buffer, state};
+use crate::{ansi, buffer, fuzzy, state};
 use crate::ansi::TerminalXY;
 use crate::gui::{ActionToExecute, ActionToTake, ActionType, HighlightDrawn, ViewType};
 use crate::gui::terminal::CursorPos;
@@ -17,6 +17,7 @@ pub struct TableGUI {
     grid_slots: TerminalXY,
     preceding_cur: usize,
     succeeding_cur: usize,
+    /// Indices into the hint's ranked matches.
     hints_iterator: Vec<usize>,
 }
 
@@ -140,24 +141,14 @@ impl super::GUITrait for TableGUI {
         }
 
         // Find relevant hints
-        // TODO: This is a bit of a mess
-        let mut disregard = 0;
-        let mut arg = String::new();
         let mut hint: &[String] = &[];
+        let mut ranked: &[(usize, fuzzy::Match)] = &[];
         if let Some(h) = buffer.get_curr_hint_safe() {
-            arg = h.0;
             hint = h.1.get_selection();
-            disregard = h.1.disregard();
+            ranked = h.1.ranked();
         };
 
-        let mut hint_indexes = Vec::with_capacity(grid_num_slots);
-        for (i, s) in hint.iter().enumerate() {
-            if s.starts_with(&arg[disregard..]) {
-                hint_indexes.push(i);
-            }
-        }
-
-        let num_hints = hint_indexes.len();
+        let num_hints = ranked.len();
 
         // Reset table if hints have changed
         if self.prev_len != num_hints {
@@ -201,9 +192,7 @@ impl super::GUITrait for TableGUI {
 
             (0..upper)
                 .map(|x| {
-                    let i = (x + (self.table_scroll * grid_slots.0 as usize))
-                        .rem_euclid(num_hints);
-                    hint_indexes[i]
+                    (x + (self.table_scroll * grid_slots.0 as usize)).rem_euclid(num_hints)
                 }).collect()
         };
         let ind = (self.cursor_pos.1 as usize + self.table_scroll)
@@ -213,8 +202,7 @@ impl super::GUITrait for TableGUI {
 
         // Return action
         if should_set_closest {
-            let hint_ind = hint_indexes[ind];
-            let hint = hint[hint_ind].clone();
+            let hint = hint[ranked[ind].0].clone();
             ActionToTake::WriteBuffer(ActionType::Other(ActionToExecute::SetClosestMatch(hint)))
         } else {
             ActionToTake::WriteBuffer(ActionType::Standard)
@@ -232,16 +220,6 @@ impl super::GUITrait for TableGUI {
         ansi::move_to((0, write_from_line));
         ansi::erase_line();
 
-        pub type AddDots = bool;
-        fn shorten_str(s: &str, max_len: usize) -> (AddDots, &str) {
-            // shorten with ...
-            if s.len() > max_len {
-                (true, &s[..max_len - 3])
-            } else {
-                (false, s)
-            }
-        }
-
         let program_state = self.program_state.borrow();
 
         {
@@ -255,19 +233,23 @@ impl super::GUITrait for TableGUI {
 
         {
             let mut hints: &[String] = &[];
+            let mut ranked: &[(usize, fuzzy::Match)] = &[];
             if let Some(hint) = buf.get_curr_hint_safe() {
                 hints = hint.1.get_selection();
+                ranked = hint.1.ranked();
             }
+            let theme = &program_state.config.theme;
 
             let max_len = program_state.config.gui.table.max_field_len as usize;
             let mut first = true;
             let mut cursor_drawn = HighlightDrawn::Before;
             let mut row = 0;
             let mut col = 0;
-            let mut style = &program_state.config.theme.console_secondary.normal;
+            let mut style = (&theme.console_secondary.normal, &theme.console_main.normal);
 
             for i in &self.hints_iterator {
-                let item = &hints[*i];
+                let (item_ind, m) = &ranked[*i];
+                let item = &hints[*item_ind];
 
                 if col % self.grid_slots.0 == 0 && !first {
                     ansi::move_down(1);
@@ -281,20 +263,21 @@ impl super::GUITrait for TableGUI {
                 if cursor_drawn == HighlightDrawn::Before
                     && row == self.cursor_pos.1 && col == self.cursor_pos.0 {
                     cursor_drawn = HighlightDrawn::During;
-                    style = &program_state.config.theme.console_secondary.highlighted;
+                    style = (&theme.console_secondary.highlighted, &theme.console_main.highlighted);
                 } else if cursor_drawn == HighlightDrawn::During {
                     cursor_drawn = HighlightDrawn::After;
-                    style = &program_state.config.theme.console_secondary.normal;
+                    style = (&theme.console_secondary.normal, &theme.console_main.normal);
                 };
 
-                let l = match shorten_str(item, max_len) {
+                let l = match super::shorten_str(item, max_len) {
                     (true, s) => {
-                        super::output_str(style, format!("{}...", s).as_str());
-                        s.len() + 3
+                        super::output_matched(style.0, style.1, s, &m.positions, 0);
+                        super::output_str(style.0, "...");
+                        s.chars().count() + 3
                     }
                     (false, s) => {
-                        super::output_str(style, format!("{}", s).as_str());
-                        s.len()
+                        super::output_matched(style.0, style.1, s, &m.positions, 0);
+                        s.chars().count()
                     }
                 };
                 for _ in 0..(max_len - l) {
@@ -1,6 +1,6 @@
 use std::cell::RefCell;
 use std::rc::Rc;
-use crate::{state, utils, input, buffer, enums, ansi, hints};
+use crate::{state, utils, input, buffer, enums, ansi, hints, execution, history};
 use crate::ansi::TerminalXY;
 use crate::config::theme;
 use crate::gui::{ActionToTake, ActionType};
@@ -37,6 +37,10 @@ pub struct TerminalGUI {
     current_line: u16,
 
     short_cwd: String,
+    /// Duration of the last command, only set if it exceeded `show_duration_after_ms`.
+    last_duration: String,
+    /// The history scope, unless it is the global one.
+    scope_tag: String,
 }
 
 
@@ -45,6 +49,8 @@ impl TerminalGUI
     pub fn init(program_state: Rc<RefCell<state::ProgramState>>) -> Self {
         Self {
             short_cwd: String::new(),
+            last_duration: String::new(),
+            scope_tag: String::new(),
             program_state,
             additional_view: None,
             current_line: 0,
@@ -55,6 +61,20 @@ impl TerminalGUI
         self.current_line = line;
     }
 
+    pub fn set_last_timing(&mut self, timing: Option<execution::CommandTiming>) {
+        let threshold = self.program_state.borrow().config.execution.show_duration_after_ms;
+        self.last_duration = match timing {
+            Some(t) if t.wall.as_millis() >= threshold as u128 => {
+                format!("{} ", utils::format_duration(t.wall))
+            }
+            _ => String::new(),
+        };
+    }
+
+    fn prompt_len(&self) -> usize {
+        self.last_duration.len() + self.scope_tag.len() + self.short_cwd.len()
+    }
+
     pub fn set_using(&mut self, view: Option<Box<dyn GUITrait>>) {
         self.additional_view = view;
     }
@@ -70,6 +90,8 @@ impl TerminalGUI
     pub fn output_path(&self) {
         let program_state = self.program_state.borrow();
         let theme = &program_state.config.theme;
+        super::output_str(&theme.console_secondary.normal, &self.last_duration);
+        super::output_str(&theme.console_secondary.normal, &self.scope_tag);
         super::output_str(&theme.console_main.normal, &self.short_cwd);
     }
 
@@ -104,12 +126,18 @@ impl TerminalGUI
             hint: &hints::Hint,
         ) {
             let disregard = hint.disregard();
-            if let Some(hint) = hint.last_closest_match() {
-                super::output_str(&style.normal, &arg[..cur_a]);
-                super::output_str(&hint_style, &hint[(cur_a - disregard)..]);
-                super::output_str(&style.normal, &arg[cur_a..]);
-            } else {
-                super::output_str(&style.normal, &arg);
+            match hint.last_closest_match() {
+                Some(hint) if arg.get(disregard..cur_a).is_some_and(|typed| hint.starts_with(typed)) => {
+                    super::output_str(&style.normal, &arg[..cur_a]);
+                    super::output_str(hint_style, &hint[(cur_a - disregard)..]);
+                    super::output_str(&style.normal, &arg[cur_a..]);
+                }
+                // A fuzzy or substring match, which Tab replaces the argument with
+                Some(hint) => {
+                    super::output_str(&style.normal, arg);
+                    super::output_str(hint_style, &format!(" -> {}", hint));
+                }
+                None => super::output_str(&style.normal, arg),
             }
         }
 
@@ -133,10 +161,12 @@ impl TerminalGUI
                 true => &theme.text,
                 false => {
                     let arg = &arg_hints[i / 2];
-                    match arg.0 {
+                    match &arg.0 {
+                        _ if !arg.1.is_valid() || buf.is_violation(i / 2) => &theme.error,
                         enums::ArgType::Executable => &theme.executable,
-                        enums::ArgType::Path => &theme.path,
-                        enums::ArgType::Text => &theme.text
+                        arg_type if arg_type.is_filesystem() => &theme.path,
+                        enums::ArgType::Text => &theme.text,
+                        _ => &theme.arg,
                     }
                 }
             };
@@ -163,8 +193,9 @@ impl TerminalGUI
                     }
                 }
                 HighlightState::AfterHighlight => {
-                    // We do not show inline hints when highlighted
-                    if !hilt_active && i % 2 == 0
+                    // We do not show inline hints when highlighted, or when there is a history
+                    // suggestion in their place
+                    if !hilt_active && i % 2 == 0 && buf.get_suggestion().is_none()
                         && (cur_a >= start && cur_b <= stop) {
                         // if !hilt_active && cur_a >= start && cur_b < stop {
                         handle_suggestion_arg(
@@ -181,6 +212,38 @@ impl TerminalGUI
         }
     }
 
+    /// Only drawn up to the end of the line the buffer ends on, so it never pushes down a view.
+    /// Without a suggestion, the positional arguments and required flags the command still needs
+    /// are drawn instead.
+    fn output_suggestion(&self, buf: &buffer::InputBuffer, term_size: TerminalXY) {
+        if term_size.0 == 0 {
+            return;
+        }
+        let theme = &self.program_state.borrow().config.theme;
+        let (parts, inlay_len) = match buf.get_suggestion() {
+            Some(suggestion) => (vec![(&theme.console_secondary.normal, suggestion.to_string())], 0),
+            None => {
+                let args = buf.get_missing_args().iter()
+                    .map(|arg| (&theme.console_secondary.normal, format!(" {}", arg)));
+                let flags = buf.get_missing_flags().iter()
+                    .map(|flag| (&theme.error.normal, format!(" {}", flag)));
+                // They follow the inlay hint of an empty argument at the end
+                let inlay_len = match buf.get_curr_hint_safe() {
+                    Some((arg, hint)) if arg.is_empty() => hint.last_closest_match().map_or(0, String::len),
+                    _ => 0,
+                };
+                (args.chain(flags).collect(), inlay_len)
+            }
+        };
+        let col = (self.prompt_len() + buf.len() + inlay_len) % term_size.0 as usize;
+        let mut room = (term_size.0 as usize).saturating_sub(col + 1);
+        for (style, part) in parts {
+            let part = part.chars().take(room).collect::<String>();
+            room -= part.chars().count();
+            super::output_str(style, &part);
+        }
+    }
+
     pub fn action_before_write(
         &mut self,
         buf: &buffer::InputBuffer,
@@ -222,6 +285,7 @@ impl TerminalGUI
 
         self.output_path();
         self.output_buffer(buf);
+        self.output_suggestion(buf, term_size);
 
         if let Some(view) = &mut self.additional_view {
             view.write_output(event, term_size, self.current_line + cursor_position.1 + 1, buf)
@@ -248,18 +312,22 @@ impl TerminalGUI
         }
 
         self.short_cwd = utils::short_path(&self.program_state.borrow().current_working_directory);
+        self.scope_tag = match self.program_state.borrow().history_scope {
+            history::ScopeKind::Global => String::new(),
+            scope => format!("[{}] ", scope.name()),
+        };
 
-        let upto_end = buffer.len() + self.short_cwd.len();
-        let upto_cursor = buffer.main_cur().position() + self.short_cwd.len();
+        let upto_end = buffer.len() + self.prompt_len();
+        let upto_cursor = buffer.main_cur().position() + self.prompt_len();
         let upto_curr_arg = {
             if buffer.num_args() == 0 {
-                0 + self.short_cwd.len()
+                self.prompt_len()
             } else {
                 let mut ind = buffer.get_curr_arg() * 2;
                 if ind >= buffer.get_splits().len() {
                     ind -= 2;
                 }
-                buffer.get_splits()[ind] + self.short_cwd.len()
+                buffer.get_splits()[ind] + self.prompt_len()
             }
         };
 
@@ -0,0 +1,43 @@
+use std::{path, time};
+
+use crate::enums::{ArgType, Shell};
+use super::worker::HintWorker;
+
+/// The lines the command of `arg_type` printed last, see `HintWorker::command`.
+fn output(arg_type: &ArgType, shell: &Shell, cwd: &path::Path, worker: &mut HintWorker) -> Vec<String> {
+    match arg_type {
+        ArgType::Dynamic { command, cache_secs } => {
+            worker.command(shell, command, cwd, time::Duration::from_secs(*cache_secs))
+        }
+        _ => vec![],
+    }
+}
+
+pub fn make_dynamic_hint(
+    arg_type: &ArgType,
+    arg: &str,
+    inlay: Option<String>,
+    ranking: super::Ranking,
+    (shell, cwd): (&Shell, &path::Path),
+    worker: &mut HintWorker,
+) -> super::Hint {
+    let mut hint = super::Hint::new(output(arg_type, shell, cwd, worker), inlay, ranking);
+    hint.closest_match(arg);
+    hint
+}
+
+/// Picks up output that arrived since, and has the command run again once it is older than its
+/// `cache_secs`.
+pub fn update_dynamic_hint(
+    arg_type: &ArgType,
+    arg: &str,
+    hint: &mut super::Hint,
+    (shell, cwd): (&Shell, &path::Path),
+    worker: &mut HintWorker,
+) {
+    let lines = output(arg_type, shell, cwd, worker);
+    if lines != hint.get_selection() {
+        hint.set_selection(lines);
+    }
+    hint.closest_match(arg);
+}
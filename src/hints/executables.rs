@@ -1,51 +1,195 @@
-use std::{path, process};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::{env, ffi, fs, path, sync, time};
+use crate::config::command;
+use crate::execution;
+
+/// Executables run at least this often without a `config/commands/<name>.toml` are suggested one.
+pub const SUGGEST_CONFIG_MIN_USES: u32 = 10;
+
+/// Every executable on `PATH`, scanned natively rather than through a shell.
+#[derive(Debug)]
+pub struct ExecutableIndex {
+    path_var: Option<ffi::OsString>,
+    /// Each `PATH` directory with its modification time when it was scanned.
+    dirs: Vec<(path::PathBuf, Option<time::SystemTime>)>,
+    /// Full paths of every executable, in `PATH` order, so the first is the one that runs.
+    executables: BTreeMap<String, Vec<path::PathBuf>>,
+}
 
 lazy_static::lazy_static! {
-    static ref EXECUTABLES: Vec<String> = get_executables();
-}
-
-fn get_executables_windows() -> Result<process::Output, std::io::Error> {
-    process::Command::new("where")
-        .args(["*.exe"])
-        .output()
-}
-
-fn get_executables_linux() -> Result<process::Output, std::io::Error> {
-    process::Command::new("sh")
-        .arg("-c")
-        .arg("echo $PATH | tr ':' '\\n' | xargs -I {} find {} -maxdepth 1 -type f -executable -print")
-        .output()
-
-    // process::Command::new("echo")
-    //     .args(["$PATH", "|", "tr", ":", "\n", "|", "xargs", "-I", "{}", "find", "{}", "-maxdepth", "1", "-type", "f", "-executable", "-print"])
-    //     .output()
-}
-
-fn get_executables() -> Vec<String> {
-    let (split, output) = if cfg!(target_os = "windows") {
-        ("\r\n", get_executables_windows())
-    } else if cfg!(target_os = "linux") {
-        ("\n", get_executables_linux())
-    } else {
-        unimplemented!()
-    };
-    let output = output
-        .unwrap();
-    let output = String::from_utf8(output.stdout).unwrap();
-    let mut executables = output
-        .split(split)
-        .filter(|line| !line.is_empty())
-        .map(|line| {
-            let p = path::PathBuf::from(line);
-            p.file_name().unwrap().to_str().unwrap().to_string()
-        })
-        .collect::<Vec<String>>();
-    executables.sort();
-    executables
+    /// Swapped out whole when rescanned, so that hints never wait for a scan to finish.
+    static ref INDEX: sync::RwLock<sync::Arc<ExecutableIndex>> = sync::RwLock::new(sync::Arc::new(ExecutableIndex::scan()));
+}
+
+fn modified(dir: &path::Path) -> Option<time::SystemTime> {
+    fs::metadata(dir).and_then(|metadata| metadata.modified()).ok()
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    metadata.is_file()
+}
+
+#[cfg(unix)]
+fn is_executable_name(_name: &str) -> bool {
+    true
+}
+
+#[cfg(not(unix))]
+fn is_executable_name(name: &str) -> bool {
+    name.to_lowercase().ends_with(".exe")
+}
+
+impl ExecutableIndex {
+    pub fn scan() -> Self {
+        Self::scan_path(env::var_os("PATH"))
+    }
+
+    fn scan_path(path_var: Option<ffi::OsString>) -> Self {
+        let mut dirs: Vec<(path::PathBuf, Option<time::SystemTime>)> = vec![];
+        let mut executables: BTreeMap<String, Vec<path::PathBuf>> = BTreeMap::new();
+
+        for dir in path_var.iter().flat_map(env::split_paths) {
+            // A directory listed twice can only ever run from its first listing
+            if dir.as_os_str().is_empty() || dirs.iter().any(|(d, _)| *d == dir) {
+                continue;
+            }
+            dirs.push((dir.clone(), modified(&dir)));
+            let entries = match fs::read_dir(&dir) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.flatten() {
+                let name = match entry.file_name().into_string() {
+                    Ok(name) if is_executable_name(&name) => name,
+                    _ => continue,
+                };
+                // Follows symlinks, which is how many package managers install binaries
+                if fs::metadata(entry.path()).is_ok_and(|metadata| is_executable(&metadata)) {
+                    executables.entry(name).or_default().push(entry.path());
+                }
+            }
+        }
+
+        Self { path_var, dirs, executables }
+    }
+
+    /// Whether `PATH`, or any directory on it, changed since the scan.
+    pub fn is_stale(&self) -> bool {
+        self.is_stale_with(env::var_os("PATH"))
+    }
+
+    fn is_stale_with(&self, path_var: Option<ffi::OsString>) -> bool {
+        path_var != self.path_var
+            || self.dirs.iter().any(|(dir, scanned)| modified(dir) != *scanned)
+    }
+
+    /// The path `name` runs from.
+    pub fn resolve(&self, name: &str) -> Option<&path::Path> {
+        self.executables.get(name).and_then(|paths| paths.first()).map(path::PathBuf::as_path)
+    }
+
+    /// Every path `name` is found at, in `PATH` order.
+    pub fn all_paths(&self, name: &str) -> &[path::PathBuf] {
+        self.executables.get(name).map_or(&[], Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.executables.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.executables.is_empty()
+    }
+
+    pub fn num_dirs(&self) -> usize {
+        self.dirs.len()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item=&str> {
+        self.executables.keys().map(String::as_str)
+    }
+}
+
+/// The index as last scanned, which may be stale, see `refresh`.
+pub fn current() -> sync::Arc<ExecutableIndex> {
+    INDEX.read().unwrap_or_else(sync::PoisonError::into_inner).clone()
+}
+
+fn replace(index: ExecutableIndex) -> sync::Arc<ExecutableIndex> {
+    let index = sync::Arc::new(index);
+    *INDEX.write().unwrap_or_else(sync::PoisonError::into_inner) = index.clone();
+    index
+}
+
+/// Scans `PATH` again if it is stale, returning whether it was. Run by `worker::HintWorker`, as
+/// the scan can take a while.
+pub fn refresh() -> bool {
+    let stale = current().is_stale();
+    if stale {
+        replace(ExecutableIndex::scan());
+    }
+    stale
+}
+
+/// Scans `PATH` again even if nothing seems to have changed, e.g. after a binary was replaced
+/// within the same second.
+pub fn rehash() -> sync::Arc<ExecutableIndex> {
+    replace(ExecutableIndex::scan())
+}
+
+/// Functions bash exports to its children as `BASH_FUNC_<name>%%`, which commands run with
+/// `bash -c` can call.
+fn exported_functions() -> impl Iterator<Item=String> {
+    env::vars_os().filter_map(|(key, _)| {
+        let key = key.into_string().ok()?;
+        Some(key.strip_prefix("BASH_FUNC_")?.strip_suffix("%%")?.to_string())
+    })
 }
 
-pub fn make_executables_hint(arg: &str) -> super::Hint {
-    let mut hint = super::Hint::new(EXECUTABLES.clone(), None);
+/// Everything that can be run as a command: executables on `PATH`, reserved commands, commands
+/// configured in `commands/`, which act as aliases, and exported functions. Sorted and without
+/// duplicates.
+pub fn candidates(commands: &[command::ConfigCommand]) -> Vec<String> {
+    let mut candidates = current().names().map(str::to_string).collect::<BTreeSet<String>>();
+    candidates.extend(execution::reserved_names().map(str::to_string));
+    candidates.extend(commands.iter().map(|command| command.exe_name.clone()));
+    candidates.extend(exported_functions());
+    candidates.into_iter().collect()
+}
+
+/// Whether `name` could be given a `commands/<name>.toml`, i.e. it has none and isn't built in.
+pub fn lacks_config(name: &str, commands: &[command::ConfigCommand]) -> bool {
+    !commands.iter().any(|command| command.exe_name == name) && !execution::is_reserved(name)
+}
+
+/// Executables run at least `SUGGEST_CONFIG_MIN_USES` times without a config are described with a
+/// suggestion to add one. `uses` are the run counts `history stats` lists them by, rather than the
+/// decaying scores of `ranking`, so the two suggest the same executables.
+pub fn make_executables_hint(
+    arg: &str,
+    ranking: super::Ranking,
+    commands: &[command::ConfigCommand],
+    uses: &HashMap<String, u32>,
+) -> super::Hint {
+    let candidates = candidates(commands);
+    let descriptions = candidates
+        .iter()
+        .map(|name| match uses.get(name) {
+            Some(uses) if *uses >= SUGGEST_CONFIG_MIN_USES && lacks_config(name, commands) => {
+                format!("Used often, consider adding commands/{}.toml", name)
+            }
+            _ => String::new(),
+        })
+        .collect();
+    let mut hint = super::Hint::new(candidates, None, ranking);
+    hint.set_descriptions(descriptions);
     hint.closest_match(arg);
     hint
 }
@@ -53,3 +197,71 @@ pub fn make_executables_hint(arg: &str) -> super::Hint {
 pub fn update_executables_hint(arg: &str, hint: &mut super::Hint) {
     hint.closest_match(arg);
 }
+
+/// The subcommands of `parent`, which stand in for the executable of the rest of the arguments.
+pub fn make_subcommands_hint(arg: &str, ranking: super::Ranking, parent: &command::ConfigCommand) -> super::Hint {
+    let mut hint = super::Hint::new(parent.subcommand_names(), None, ranking);
+    hint.closest_match(arg);
+    hint
+}
+
+/// Every name of the flags of `cmd`, each described by the flag it names.
+pub fn make_flags_hint(arg: &str, ranking: super::Ranking, cmd: &command::ConfigCommand) -> super::Hint {
+    let (names, descriptions) = cmd.flag_names().into_iter().unzip();
+    let mut hint = super::Hint::new(names, None, ranking);
+    hint.set_descriptions(descriptions);
+    hint.closest_match(arg);
+    hint
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_executable_index_precedence() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let root = env::temp_dir().join(format!("whale-executables-{}", std::process::id()));
+        let (first, second) = (root.join("with space"), root.join("second"));
+        for dir in [&first, &second] {
+            fs::create_dir_all(dir).unwrap();
+            fs::write(dir.join("tool"), "").unwrap();
+            fs::set_permissions(dir.join("tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        }
+        fs::write(first.join("not-executable"), "").unwrap();
+
+        let path_var = env::join_paths([&first, &second]).ok();
+        let index = ExecutableIndex::scan_path(path_var.clone());
+        assert_eq!(index.resolve("tool"), Some(first.join("tool").as_path()));
+        assert_eq!(index.all_paths("tool"), [first.join("tool"), second.join("tool")]);
+        assert_eq!(index.resolve("not-executable"), None);
+
+        assert!(!index.is_stale_with(path_var.clone()));
+        assert!(index.is_stale_with(Some(second.clone().into_os_string())));
+        fs::File::open(&second).unwrap().set_modified(time::UNIX_EPOCH).unwrap();
+        assert!(index.is_stale_with(path_var));
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_suggest_config_description() {
+        // A high score from recent use alone isn't enough, it takes as many runs as `history stats`
+        let usage = HashMap::from([("sh".to_string(), 20.0)]);
+        let ranking = crate::hints::Ranking { usage: std::rc::Rc::new(usage), ..Default::default() };
+        let hint = make_executables_hint("sh", ranking.clone(), &[], &HashMap::new());
+        let i = hint.get_selection().iter().position(|name| name == "sh").unwrap();
+        assert_eq!(hint.description(i), None);
+
+        let uses = HashMap::from([("sh".to_string(), SUGGEST_CONFIG_MIN_USES)]);
+        let hint = make_executables_hint("sh", ranking.clone(), &[], &uses);
+        assert_eq!(hint.description(i), Some("Used often, consider adding commands/sh.toml"));
+
+        let sh_cmd = command::ConfigCommand { exe_name: "sh".to_string(), ..Default::default() };
+        let hint = make_executables_hint("sh", ranking, &[sh_cmd], &uses);
+        assert_eq!(hint.description(i), None);
+    }
+}
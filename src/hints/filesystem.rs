@@ -1,61 +1,70 @@
-use std::{fs, path};
+use std::path;
 
-fn get_files_in_directory(dir: &path::PathBuf) -> Vec<String> {
-    match fs::read_dir(dir) {
-        Err(_) => vec![],
-        Ok(files) => files
-            .into_iter()
-            .map(|f| {
-                f.unwrap()
-                    .path()
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_string()
-            })
-            .collect(),
+use crate::enums::ArgType;
+use super::worker::{Entry, HintWorker};
+
+/// Whether `entry` is suggested for an argument of `arg_type`. Directories are always suggested
+/// for files, as the file may be inside them.
+fn accepts(arg_type: &ArgType, entry: &Entry) -> bool {
+    match arg_type {
+        ArgType::Directory => entry.is_dir,
+        ArgType::File { extensions } => {
+            entry.is_dir
+                || extensions.is_empty()
+                || path::Path::new(&entry.name).extension().is_some_and(|ext| {
+                    let ext = ext.to_string_lossy();
+                    extensions.iter().any(|e| e.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+                })
+        }
+        _ => true,
     }
 }
 
-pub fn make_directory_hints(dir: Option<(path::PathBuf, super::Disregard, String)>, inlay: Option<String>) -> super::Hint {
-    if dir.is_none() {
-        return super::Hint::new(vec![], inlay);
+/// Sets the selection to the entries of `dir` accepted for `arg_type`. The argument is only
+/// invalid if it names an entry of the wrong kind, as anything else might not exist yet or not
+/// have been listed yet.
+fn set_entries(hint: &mut super::Hint, arg_type: &ArgType, entries: Vec<Entry>, arg: &str) {
+    hint.set_valid(entries.iter().find(|entry| entry.name == arg).is_none_or(|entry| accepts(arg_type, entry)));
+    hint.set_selection(entries.into_iter().filter(|entry| accepts(arg_type, entry)).map(|entry| entry.name).collect());
+}
+
+/// Entries are whatever `worker` knows of the directory so far, see `HintWorker::directory`.
+pub fn make_directory_hints(
+    dir: Option<(path::PathBuf, super::Disregard, String)>,
+    inlay: Option<String>,
+    ranking: super::Ranking,
+    arg_type: &ArgType,
+    worker: &mut HintWorker,
+) -> super::Hint {
+    let mut hint = super::Hint::new(vec![], inlay, ranking);
+    if let Some((dir, disregard, arg)) = dir {
+        set_entries(&mut hint, arg_type, worker.directory(&dir), &arg);
+        hint.set_set_using(dir);
+        hint.set_disregard(disregard);
+        hint.closest_match(&arg);
     }
-    let (dir, disregard, arg) = dir.unwrap();
-    let available_files = get_files_in_directory(&dir);
-    let mut hint = super::Hint::new(available_files, inlay);
-    hint.set_set_using(dir);
-    hint.set_disregard(disregard);
-    hint.closest_match(&arg);
     hint
 }
 
-pub fn update_directory_hints(new_dir: &Option<(path::PathBuf, super::Disregard, String)>, hints: &mut super::Hint) {
+pub fn update_directory_hints(
+    new_dir: &Option<(path::PathBuf, super::Disregard, String)>,
+    hints: &mut super::Hint,
+    arg_type: &ArgType,
+    worker: &mut HintWorker,
+) {
     match new_dir {
         None => {
             hints.set_selection(vec![]);
             hints.set_set_using(path::PathBuf::new());
             hints.set_disregard(0);
+            hints.set_valid(true);
         }
         Some((new_dir, disregard, arg)) => {
             hints.set_disregard(*disregard);
-            if !new_dir.exists() {
-                hints.set_selection(vec![]);
-                hints.set_set_using(path::PathBuf::new());
-            } else {
-                let available_files = get_files_in_directory(new_dir);
-                hints.set_selection(available_files);
-                hints.set_set_using(new_dir.clone());
-                hints.closest_match(&arg);
-            }
+            // A directory that doesn't exist is simply listed as empty by the worker
+            set_entries(hints, arg_type, worker.directory(new_dir), arg);
+            hints.set_set_using(new_dir.clone());
+            hints.closest_match(arg);
         }
     }
-    //
-    // let new_dir = new_dir.as_ref().unwrap();
-    // if new_dir != hints.set_using() {
-    //     let available_files = get_files_in_directory(new_dir);
-    //     hints.set_selection(available_files);
-    //     hints.set_set_using(new_dir.clone());
-    // }
 }
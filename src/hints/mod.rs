@@ -1,37 +1,85 @@
-use crate::utils;
+use crate::fuzzy;
+use std::collections::HashMap;
 use std::path;
+use std::rc::Rc;
 
+pub mod dynamic;
 pub mod executables;
 pub mod filesystem;
+pub mod values;
+pub mod worker;
 
 pub type Disregard = usize;
 
+/// Match scores are in the tens, see `fuzzy`, so an item used daily for weeks outranks an item
+/// that matches slightly better.
+const USAGE_WEIGHT: f64 = 8.0;
+
+/// How the items of a hint are ordered.
+#[derive(Debug, Clone, Default)]
+pub struct Ranking {
+    pub match_mode: fuzzy::MatchMode,
+    /// Scores of the items used before, from `history::usage`.
+    pub usage: Rc<HashMap<String, f64>>,
+}
+
+impl Ranking {
+    fn score(&self, item: &str, m: &fuzzy::Match) -> i64 {
+        let usage = self.usage.get(item).map_or(0.0, |usage| usage.ln_1p());
+        m.score + (usage * USAGE_WEIGHT) as i64
+    }
+}
+
 #[derive(Debug)]
 pub struct Hint {
     /// Assumes selection is alphabetically sorted!
     selection: Vec<String>,
+    /// One line about each item of `selection`, if there is anything to say, e.g. what a flag does.
+    descriptions: Vec<String>,
     inlay: Option<String>,
     set_using: path::PathBuf,
     disregard: Disregard,
     last_closest_match: Option<String>,
+    /// Whether the argument is acceptable for its `ArgType`, otherwise it is drawn as an error.
+    valid: bool,
+    ranking: Ranking,
+    /// Indices into `selection` of the items matching the argument, best first, with the positions
+    /// of the matched characters.
+    ranked: Vec<(usize, fuzzy::Match)>,
 }
 
 impl Hint {
-    pub fn new(selection: Vec<String>, inlay: Option<String>) -> Self {
+    pub fn new(selection: Vec<String>, inlay: Option<String>, ranking: Ranking) -> Self {
         Self {
             selection,
+            descriptions: vec![],
             inlay,
             set_using: path::PathBuf::new(),
             disregard: 0,
             last_closest_match: None,
+            valid: true,
+            ranking,
+            ranked: vec![],
         }
     }
 
     pub fn set_selection(&mut self, selection: Vec<String>) {
         self.last_closest_match = None;
+        self.ranked.clear();
+        self.descriptions.clear();
         self.selection = selection;
     }
 
+    /// In the same order as the selection.
+    pub fn set_descriptions(&mut self, descriptions: Vec<String>) {
+        self.descriptions = descriptions;
+    }
+
+    /// Of the item at `i` in the selection, if it has one.
+    pub fn description(&self, i: usize) -> Option<&str> {
+        self.descriptions.get(i).map(String::as_str).filter(|description| !description.is_empty())
+    }
+
     pub fn get_selection(&self) -> &[String] {
         &self.selection
     }
@@ -44,6 +92,11 @@ impl Hint {
         self.set_using = set_using;
     }
 
+    /// Takes effect from the next `closest_match`.
+    pub fn set_ranking(&mut self, ranking: Ranking) {
+        self.ranking = ranking;
+    }
+
     pub fn set_inlay(&mut self, inlay: Option<String>) {
         self.inlay = inlay;
     }
@@ -56,37 +109,43 @@ impl Hint {
         self.disregard
     }
 
+    pub fn set_valid(&mut self, valid: bool) {
+        self.valid = valid;
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+
     pub fn last_closest_match(&self) -> Option<&String> {
         self.last_closest_match.as_ref()
     }
 
-    // TODO: Cleanup more rubbish
+    /// Ranks the selection against `s` and picks the best match. Items are ordered by how well
+    /// they match and how much they were used, and otherwise keep their alphabetical order.
     pub fn closest_match(&mut self, s: &str) {
-        let x = if s.is_empty() && self.inlay.is_some() {
-            Some(self.inlay.as_ref().unwrap().to_string())
-        } else if s.is_empty() {
-            self.selection.first().map(|x| x.clone())
-        } else {
-            match utils::first_item(&self.selection, s) {
-                Some(ind) => Some(self.selection[ind].clone()),
-                None => None,
-            }
+        let ranking = &self.ranking;
+        self.ranked = self.selection
+            .iter()
+            .enumerate()
+            .filter_map(|(i, item)| fuzzy::find(ranking.match_mode, s, item).map(|m| (i, m)))
+            .collect();
+        let selection = &self.selection;
+        self.ranked.sort_by_cached_key(|(i, m)| std::cmp::Reverse(ranking.score(&selection[*i], m)));
+
+        self.last_closest_match = match (&self.inlay, s.is_empty()) {
+            (Some(inlay), true) => Some(inlay.clone()),
+            _ => self.ranked.first().map(|(i, _)| self.selection[*i].clone()),
         };
-        self.last_closest_match = x;
     }
 
     pub fn set_closest_match(&mut self, s: String) {
         self.last_closest_match = Some(s);
     }
 
-    pub fn filtered_items(&self, s: String) -> impl Iterator<Item=&str> {
-        self.selection.iter().filter_map(move |v| {
-            if v.starts_with(&s) {
-                Some(v.as_str())
-            } else {
-                None
-            }
-        })
+    /// The items matching the argument given to `closest_match`, best first.
+    pub fn ranked(&self) -> &[(usize, fuzzy::Match)] {
+        &self.ranked
     }
 }
 
@@ -94,10 +153,41 @@ impl Default for Hint {
     fn default() -> Self {
         Self {
             selection: vec![],
+            descriptions: vec![],
             inlay: None,
             set_using: path::PathBuf::new(),
             disregard: 0,
             last_closest_match: None,
+            valid: true,
+            ranking: Ranking::default(),
+            ranked: vec![],
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closest_match_ranking() {
+        let selection = vec!["cargo".to_string(), "cat".to_string(), "git-checkout".to_string()];
+        let ranking = |match_mode| Ranking { match_mode, usage: Rc::default() };
+        let mut hint = Hint::new(selection.clone(), None, ranking(fuzzy::MatchMode::Fuzzy));
+        hint.closest_match("gco");
+        assert_eq!(hint.last_closest_match().map(String::as_str), Some("git-checkout"));
+        assert_eq!(hint.ranked()[0].1.positions, vec![0, 4, 9]);
+
+        let mut hint = Hint::new(selection, None, ranking(fuzzy::MatchMode::Prefix));
+        hint.closest_match("ca");
+        assert_eq!(hint.ranked().iter().map(|(i, _)| *i).collect::<Vec<usize>>(), vec![0, 1]);
+
+        // The more used item goes first, even if it comes later alphabetically
+        let usage = HashMap::from([("cat".to_string(), 20.0)]);
+        hint.set_ranking(Ranking { match_mode: fuzzy::MatchMode::Prefix, usage: Rc::new(usage) });
+        hint.closest_match("ca");
+        assert_eq!(hint.last_closest_match().map(String::as_str), Some("cat"));
+        hint.closest_match("gco");
+        assert_eq!(hint.last_closest_match(), None);
+    }
+}
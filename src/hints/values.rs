@@ -0,0 +1,176 @@
+//! Hints for arguments that aren't paths or executables: choices, numbers, and names drawn from
+//! the system, such as users, hosts and processes. Each `ArgType` also decides which arguments are
+//! valid, which `TerminalGUI` draws as errors otherwise.
+
+use std::{env, fs, path};
+use crate::enums::ArgType;
+
+/// Names of the signals common to Linux and the BSDs, in the order of their numbers on Linux.
+const SIGNALS: &[&str] = &[
+    "HUP", "INT", "QUIT", "ILL", "TRAP", "ABRT", "BUS", "FPE", "KILL", "USR1", "SEGV", "USR2",
+    "PIPE", "ALRM", "TERM", "CHLD", "CONT", "STOP", "TSTP", "TTIN", "TTOU", "URG", "XCPU", "XFSZ",
+    "VTALRM", "PROF", "WINCH", "IO", "SYS",
+];
+
+/// Highest signal number, including real-time signals.
+const MAX_SIGNAL: u32 = 64;
+
+/// First field of each line of a `passwd` or `group` style file.
+fn first_fields(file: &str) -> Vec<String> {
+    fs::read_to_string(file)
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split(':').next())
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Names from `/etc/hosts`, and the hosts of `~/.ssh/config` without wildcards.
+fn hostnames() -> Vec<String> {
+    let mut hosts = vec![];
+    for line in fs::read_to_string("/etc/hosts").unwrap_or_default().lines() {
+        let line = line.split('#').next().unwrap_or_default();
+        // The first field is the address
+        hosts.extend(line.split_whitespace().skip(1).map(str::to_string));
+    }
+    if let Some(home) = env::var_os("HOME") {
+        let config = path::Path::new(&home).join(".ssh").join("config");
+        for line in fs::read_to_string(config).unwrap_or_default().lines() {
+            let mut words = line.split_whitespace();
+            if words.next().is_some_and(|keyword| keyword.eq_ignore_ascii_case("host")) {
+                hosts.extend(words.filter(|host| !host.contains(['*', '?', '!'])).map(str::to_string));
+            }
+        }
+    }
+    hosts
+}
+
+/// Ids of the running processes, in numerical order.
+fn pids() -> Vec<String> {
+    let mut pids = fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u32>().ok())
+        .collect::<Vec<u32>>();
+    pids.sort_unstable();
+    pids.into_iter().map(|pid| pid.to_string()).collect()
+}
+
+fn sorted(mut items: Vec<String>) -> Vec<String> {
+    items.sort();
+    items.dedup();
+    items
+}
+
+fn selection(arg_type: &ArgType) -> Vec<String> {
+    match arg_type {
+        ArgType::Choice { values } => values.clone(),
+        ArgType::EnvVar => sorted(env::vars_os().filter_map(|(key, _)| key.into_string().ok()).collect()),
+        ArgType::User => sorted(first_fields("/etc/passwd")),
+        ArgType::Group => sorted(first_fields("/etc/group")),
+        ArgType::Hostname => sorted(hostnames()),
+        ArgType::Pid => pids(),
+        ArgType::Signal => SIGNALS.iter().map(|signal| signal.to_string()).collect(),
+        _ => vec![],
+    }
+}
+
+/// Length of what precedes the value itself, e.g. the `user@` of a host.
+fn disregard(arg_type: &ArgType, arg: &str) -> super::Disregard {
+    match arg_type {
+        ArgType::EnvVar if arg.starts_with('$') => 1,
+        ArgType::Signal => {
+            let dash = usize::from(arg.starts_with('-'));
+            let sig = arg.get(dash..dash + 3).is_some_and(|sig| sig.eq_ignore_ascii_case("sig"));
+            dash + if sig { 3 } else { 0 }
+        }
+        ArgType::Hostname => arg.find('@').map_or(0, |at| at + 1),
+        _ => 0,
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Whether `value`, the argument without what `disregard` skips, is valid for `arg_type`.
+/// Nothing typed yet is always valid.
+fn is_valid(arg_type: &ArgType, value: &str, selection: &[String]) -> bool {
+    let is_number = !value.is_empty() && value.chars().all(|c| c.is_ascii_digit());
+    value.is_empty() || match arg_type {
+        ArgType::Choice { values } => values.iter().any(|v| v == value),
+        ArgType::Integer { min, max } => value.parse::<i64>().is_ok_and(|n| {
+            min.is_none_or(|min| n >= min) && max.is_none_or(|max| n <= max)
+        }),
+        ArgType::EnvVar => is_identifier(value),
+        ArgType::User | ArgType::Group => is_number || selection.iter().any(|name| name == value),
+        // Any name might resolve through DNS
+        ArgType::Hostname => {
+            !value.starts_with('-')
+                && value.chars().all(|c| c.is_ascii_alphanumeric() || "-._:[]".contains(c))
+        }
+        // Checked afresh, as the process may have started after the hint was made
+        ArgType::Pid => {
+            let proc = path::Path::new("/proc");
+            is_number && (!proc.is_dir() || proc.join(value).is_dir())
+        }
+        ArgType::Signal => {
+            let name = value.to_ascii_uppercase();
+            let name = name.strip_prefix("SIG").unwrap_or(&name);
+            SIGNALS.contains(&name) || value.parse::<u32>().is_ok_and(|n| n <= MAX_SIGNAL)
+        }
+        _ => true,
+    }
+}
+
+/// Hint for the arguments that aren't paths, executables or plain text.
+pub fn make_value_hint(
+    arg_type: &ArgType,
+    arg: &str,
+    inlay: Option<String>,
+    ranking: super::Ranking,
+) -> super::Hint {
+    let mut hint = super::Hint::new(selection(arg_type), inlay, ranking);
+    update_value_hint(arg_type, arg, &mut hint);
+    hint
+}
+
+pub fn update_value_hint(arg_type: &ArgType, arg: &str, hint: &mut super::Hint) {
+    let disregard = disregard(arg_type, arg);
+    let value = &arg[disregard..];
+    hint.set_disregard(disregard);
+    hint.set_valid(is_valid(arg_type, value, hint.get_selection()));
+    hint.closest_match(value);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_validation() {
+        let integer = ArgType::Integer { min: Some(1), max: Some(65535) };
+        assert!(is_valid(&integer, "22", &[]));
+        assert!(!is_valid(&integer, "0", &[]));
+        assert!(!is_valid(&integer, "2x", &[]));
+
+        assert!(is_valid(&ArgType::Signal, "sigterm", &[]));
+        assert!(is_valid(&ArgType::Signal, "9", &[]));
+        assert!(!is_valid(&ArgType::Signal, "TERMINATE", &[]));
+
+        let choice = ArgType::Choice { values: vec!["start".to_string(), "stop".to_string()] };
+        assert!(is_valid(&choice, "stop", &[]));
+        assert!(!is_valid(&choice, "sto", &[]));
+
+        assert_eq!(disregard(&ArgType::Hostname, "root@example.com"), 5);
+        assert_eq!(disregard(&ArgType::Signal, "-HUP"), 1);
+        assert_eq!(disregard(&ArgType::Signal, "-sigHUP"), 4);
+        assert!(is_valid(&ArgType::EnvVar, "_HOME2", &[]));
+        assert!(!is_valid(&ArgType::EnvVar, "2HOME", &[]));
+    }
+}
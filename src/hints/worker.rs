@@ -0,0 +1,421 @@
+//! Generates hints on a thread of their own, so that slow file systems, e.g. network mounts, or
+//! huge directories never hold up typing.
+//!
+//! The input thread asks for a directory with `HintWorker::directory`, which returns whatever is
+//! known about it straight away, however stale or partial, and has the worker list it in the
+//! background. Large directories are sent back in batches as they are read. Asking for another
+//! directory abandons a listing that is still in progress. Listings are cached on both sides of
+//! the channel, and only read again once the directory's modification time changes.
+//!
+//! Commands whose output are hints, see `ArgType::Dynamic`, likewise return their last output
+//! straight away. Each runs on a thread of its own, and is killed if it takes too long.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::{fs, path, process, thread, time};
+use crate::enums;
+
+/// Entries read before they are sent, and before the worker checks whether it was cancelled.
+const BATCH_SIZE: usize = 512;
+
+/// How long a complete listing is trusted before the worker checks the directory again.
+const REVALIDATE_AFTER: time::Duration = time::Duration::from_secs(2);
+
+/// Cached listings kept on each side, beyond which the cache is started afresh.
+const MAX_CACHED_DIRS: usize = 64;
+
+/// How long a command may run before it is killed, leaving its hint empty.
+const COMMAND_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+
+/// A command, and the directory it runs in.
+type CommandKey = (String, path::PathBuf);
+
+/// An entry of a directory listing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    /// Also true of symlinks to directories.
+    pub is_dir: bool,
+}
+
+enum Request {
+    Directory {
+        id: u64,
+        dir: path::PathBuf,
+        /// Whether the input thread has the listing cached, so an unchanged one needn't be sent.
+        cached: bool,
+    },
+    Executables,
+    Command {
+        key: CommandKey,
+        shell: enums::Shell,
+    },
+}
+
+enum Response {
+    Entries {
+        dir: path::PathBuf,
+        entries: Vec<Entry>,
+        /// The first batch replaces what was known before.
+        first: bool,
+        done: bool,
+    },
+    Unchanged(path::PathBuf),
+    Cancelled(path::PathBuf),
+    Executables {
+        changed: bool,
+    },
+    Output {
+        key: CommandKey,
+        lines: Vec<String>,
+    },
+}
+
+#[derive(Debug)]
+struct Output {
+    lines: Vec<String>,
+    in_flight: bool,
+    /// When the command last finished.
+    ran: Option<time::Instant>,
+}
+
+#[derive(Debug)]
+struct Listing {
+    entries: Vec<Entry>,
+    complete: bool,
+    in_flight: bool,
+    checked: time::Instant,
+}
+
+#[derive(Debug)]
+pub struct HintWorker {
+    requests: mpsc::Sender<Request>,
+    responses: mpsc::Receiver<Response>,
+    /// Id of the newest directory request. The worker abandons any other.
+    latest: Arc<AtomicU64>,
+    listings: HashMap<path::PathBuf, Listing>,
+    outputs: HashMap<CommandKey, Output>,
+    executables_in_flight: bool,
+    executables_changed: bool,
+}
+
+fn modified(dir: &path::Path) -> Option<time::SystemTime> {
+    fs::metadata(dir).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Reads `dir` in batches, returning `None` if a newer request arrived meanwhile.
+fn list_directory(
+    dir: &path::Path,
+    id: u64,
+    latest: &AtomicU64,
+    responses: &mpsc::Sender<Response>,
+) -> Option<Vec<Entry>> {
+    let mut entries = vec![];
+    let mut batch = vec![];
+    let mut first = true;
+    for entry in fs::read_dir(dir).into_iter().flatten().flatten() {
+        let is_dir = match entry.file_type() {
+            Ok(file_type) if file_type.is_symlink() => entry.path().is_dir(),
+            Ok(file_type) => file_type.is_dir(),
+            Err(_) => false,
+        };
+        batch.push(Entry { name: entry.file_name().to_string_lossy().into_owned(), is_dir });
+        if batch.len() == BATCH_SIZE {
+            if latest.load(Ordering::Relaxed) != id {
+                return None;
+            }
+            entries.extend(batch.iter().cloned());
+            let batch = std::mem::take(&mut batch);
+            let _ = responses.send(Response::Entries { dir: dir.to_path_buf(), entries: batch, first, done: false });
+            first = false;
+        }
+    }
+    entries.extend(batch.iter().cloned());
+    let _ = responses.send(Response::Entries { dir: dir.to_path_buf(), entries: batch, first, done: true });
+    Some(entries)
+}
+
+/// The non-empty lines `command` prints, or none if it fails or times out.
+fn run_command(shell: enums::Shell, command: &str, cwd: &path::Path) -> Vec<String> {
+    let mut exec = shell.to_exec();
+    exec.arg(command)
+        .current_dir(cwd)
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::piped())
+        .stderr(process::Stdio::null());
+    // In a process group of its own, so that what the shell starts is killed along with it
+    #[cfg(unix)]
+    std::os::unix::process::CommandExt::process_group(&mut exec, 0);
+    let mut child = match exec.spawn() {
+        Ok(child) => child,
+        Err(_) => return vec![],
+    };
+
+    // Read on another thread, so that the timeout holds even if the command never closes stdout
+    let mut stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut output = vec![];
+        let _ = stdout.read_to_end(&mut output);
+        let _ = sender.send(output);
+    });
+    let output = receiver.recv_timeout(COMMAND_TIMEOUT);
+    if output.is_err() {
+        // Once the whole group is gone, the reader gets to the end of stdout and exits as well
+        #[cfg(unix)]
+        unsafe { libc::killpg(child.id() as libc::pid_t, libc::SIGKILL) };
+        #[cfg(not(unix))]
+        let _ = child.kill();
+    }
+    match (output, child.wait()) {
+        (Ok(output), Ok(status)) if status.success() => String::from_utf8_lossy(&output)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => vec![],
+    }
+}
+
+fn run(requests: mpsc::Receiver<Request>, responses: mpsc::Sender<Response>, latest: Arc<AtomicU64>) {
+    let mut cache: HashMap<path::PathBuf, (time::SystemTime, Vec<Entry>)> = HashMap::new();
+    for request in requests {
+        let response = match request {
+            Request::Executables => Response::Executables { changed: super::executables::refresh() },
+            Request::Command { key, shell } => {
+                let responses = responses.clone();
+                thread::spawn(move || {
+                    let lines = run_command(shell, &key.0, &key.1);
+                    let _ = responses.send(Response::Output { key, lines });
+                });
+                continue;
+            }
+            Request::Directory { id, dir, .. } if latest.load(Ordering::Relaxed) != id => Response::Cancelled(dir),
+            Request::Directory { id, dir, cached } => {
+                let modified = modified(&dir);
+                match cache.get(&dir) {
+                    Some((scanned, _)) if Some(*scanned) == modified && cached => Response::Unchanged(dir),
+                    Some((scanned, entries)) if Some(*scanned) == modified => {
+                        Response::Entries { dir, entries: entries.clone(), first: true, done: true }
+                    }
+                    _ => match list_directory(&dir, id, &latest, &responses) {
+                        None => Response::Cancelled(dir),
+                        Some(entries) => {
+                            if let Some(modified) = modified {
+                                if cache.len() >= MAX_CACHED_DIRS {
+                                    cache.clear();
+                                }
+                                cache.insert(dir, (modified, entries));
+                            }
+                            continue;
+                        }
+                    },
+                }
+            }
+        };
+        if responses.send(response).is_err() {
+            // The input thread is gone
+            return;
+        }
+    }
+}
+
+impl HintWorker {
+    pub fn spawn() -> Self {
+        let (requests, worker_requests) = mpsc::channel();
+        let (worker_responses, responses) = mpsc::channel();
+        let latest = Arc::new(AtomicU64::new(0));
+        {
+            let latest = latest.clone();
+            thread::spawn(move || run(worker_requests, worker_responses, latest));
+        }
+        Self {
+            requests,
+            responses,
+            latest,
+            listings: HashMap::new(),
+            outputs: HashMap::new(),
+            executables_in_flight: false,
+            executables_changed: false,
+        }
+    }
+
+    /// What is known of the entries of `dir`, which is empty until the worker first lists it. The
+    /// listing is refreshed in the background if it is incomplete or hasn't been checked lately.
+    pub fn directory(&mut self, dir: &path::Path) -> Vec<Entry> {
+        let refresh = match self.listings.get(dir) {
+            None => true,
+            Some(listing) => !listing.in_flight && (!listing.complete || listing.checked.elapsed() >= REVALIDATE_AFTER),
+        };
+        if refresh {
+            let id = self.latest.fetch_add(1, Ordering::Relaxed) + 1;
+            if !self.listings.contains_key(dir) && self.listings.len() >= MAX_CACHED_DIRS {
+                self.listings.retain(|_, listing| listing.in_flight);
+            }
+            let listing = self.listings.entry(dir.to_path_buf()).or_insert_with(|| Listing {
+                entries: vec![],
+                complete: false,
+                in_flight: false,
+                checked: time::Instant::now(),
+            });
+            listing.in_flight = true;
+            let cached = listing.complete;
+            let _ = self.requests.send(Request::Directory { id, dir: dir.to_path_buf(), cached });
+        }
+        self.listings.get(dir).map_or_else(Vec::new, |listing| listing.entries.clone())
+    }
+
+    /// The lines `command` last printed when run in `cwd`, which are none until it first
+    /// finishes. It is run again in the background once its output is older than `max_age`.
+    pub fn command(
+        &mut self,
+        shell: &enums::Shell,
+        command: &str,
+        cwd: &path::Path,
+        max_age: time::Duration,
+    ) -> Vec<String> {
+        let key = (command.to_string(), cwd.to_path_buf());
+        if !self.outputs.contains_key(&key) && self.outputs.len() >= MAX_CACHED_DIRS {
+            self.outputs.retain(|_, output| output.in_flight);
+        }
+        let output = self.outputs.entry(key.clone()).or_insert_with(|| Output {
+            lines: vec![],
+            in_flight: false,
+            ran: None,
+        });
+        if !output.in_flight && output.ran.is_none_or(|ran| ran.elapsed() >= max_age) {
+            output.in_flight = true;
+            let _ = self.requests.send(Request::Command { key, shell: shell.clone() });
+        }
+        output.lines.clone()
+    }
+
+    /// Has the worker check whether `PATH` changed, see `executables::refresh`.
+    pub fn refresh_executables(&mut self) {
+        if !self.executables_in_flight {
+            self.executables_in_flight = true;
+            let _ = self.requests.send(Request::Executables);
+        }
+    }
+
+    /// Whether the executables were scanned again since this was last asked.
+    pub fn take_executables_changed(&mut self) -> bool {
+        std::mem::take(&mut self.executables_changed)
+    }
+
+    /// Whether the worker is still busy with anything asked of it.
+    pub fn is_pending(&self) -> bool {
+        self.executables_in_flight
+            || self.listings.values().any(|listing| listing.in_flight)
+            || self.outputs.values().any(|output| output.in_flight)
+    }
+
+    /// Takes in what the worker sent since, returning whether any hints changed.
+    pub fn receive(&mut self) -> bool {
+        let mut changed = false;
+        while let Ok(response) = self.responses.try_recv() {
+            match response {
+                Response::Entries { dir, entries, first, done } => {
+                    if let Some(listing) = self.listings.get_mut(&dir) {
+                        if first {
+                            listing.entries.clear();
+                            listing.complete = false;
+                        }
+                        listing.entries.extend(entries);
+                        if done {
+                            listing.complete = true;
+                            listing.in_flight = false;
+                            listing.checked = time::Instant::now();
+                        }
+                        changed = true;
+                    }
+                }
+                Response::Unchanged(dir) => {
+                    if let Some(listing) = self.listings.get_mut(&dir) {
+                        listing.in_flight = false;
+                        listing.checked = time::Instant::now();
+                    }
+                }
+                Response::Cancelled(dir) => {
+                    if let Some(listing) = self.listings.get_mut(&dir) {
+                        listing.in_flight = false;
+                    }
+                }
+                Response::Output { key, lines } => {
+                    if let Some(output) = self.outputs.get_mut(&key) {
+                        output.in_flight = false;
+                        output.ran = Some(time::Instant::now());
+                        changed |= output.lines != lines;
+                        output.lines = lines;
+                    }
+                }
+                Response::Executables { changed: rescanned } => {
+                    self.executables_in_flight = false;
+                    self.executables_changed |= rescanned;
+                    changed |= rescanned;
+                }
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hint_worker_lists_in_background() {
+        let dir = std::env::temp_dir().join(format!("whale-hint-worker-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for i in 0..BATCH_SIZE + 1 {
+            fs::write(dir.join(format!("file{}", i)), "").unwrap();
+        }
+
+        let mut worker = HintWorker::spawn();
+        assert!(worker.directory(&dir).is_empty());
+        assert!(worker.is_pending());
+        let started = time::Instant::now();
+        while worker.is_pending() && started.elapsed() < time::Duration::from_secs(5) {
+            worker.receive();
+            thread::sleep(time::Duration::from_millis(5));
+        }
+        assert_eq!(worker.directory(&dir).len(), BATCH_SIZE + 1);
+        // Checked moments ago, so it isn't asked for again
+        assert!(!worker.is_pending());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_run_command() {
+        let cwd = std::env::temp_dir();
+        let shell = enums::Shell::default();
+        assert_eq!(run_command(shell.clone(), "printf 'main\\n\\n  dev\\n'", &cwd), ["main", "dev"]);
+        assert!(run_command(shell.clone(), "echo partial; exit 1", &cwd).is_empty());
+
+        let pid_file = cwd.join(format!("whale-run-command-{}", std::process::id()));
+        let command = format!("echo $$ > {}; sleep 37; echo late", pid_file.display());
+        let started = time::Instant::now();
+        assert!(run_command(shell, &command, &cwd).is_empty());
+        assert!(started.elapsed() < COMMAND_TIMEOUT * 2);
+
+        // The shell leads the group, and neither it nor the `sleep` it started is left running
+        #[cfg(target_os = "linux")]
+        {
+            let pgid = fs::read_to_string(&pid_file).unwrap().trim().to_string();
+            let running = fs::read_dir("/proc").unwrap().flatten().filter(|entry| {
+                let stat = fs::read_to_string(entry.path().join("stat")).unwrap_or_default();
+                // The fields after the name are the state, the parent and the group
+                let fields = stat.rsplit_once(')').map_or(vec![], |(_, rest)| rest.split_whitespace().collect());
+                fields.len() > 2 && fields[0] != "Z" && fields[2] == pgid
+            });
+            assert_eq!(running.count(), 0);
+        }
+        let _ = fs::remove_file(pid_file);
+    }
+}
@@ -0,0 +1,213 @@
+//! The history data layer as a standalone process, `whale-historyd`.
+//! Unlike the server a shell runs on one of its threads, the daemon outlives the shells using it.
+//! It writes its pid next to the socket, and flushes the history to disk before exiting on
+//! `SIGTERM` or `SIGINT`.
+
+use std::{fs, io, path, process, thread, time};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::process::CommandExt;
+use std::sync::{Arc, RwLock};
+use crate::config;
+use super::data_layer::{self, HistoryRequest, HistoryResponse};
+use super::encryption;
+use super::protocol::{self, HistoryError};
+
+pub const BINARY_NAME: &str = "whale-historyd";
+
+const PIDFILE_NAME: &str = "historyd.pid";
+
+/// How long `start` and `stop` wait for the daemon to come up or go away.
+const STARTUP_TIMEOUT: time::Duration = time::Duration::from_secs(2);
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(20);
+
+pub fn pidfile_path(config: &config::FullConfig) -> path::PathBuf {
+    protocol::socket_path(config).with_file_name(PIDFILE_NAME)
+}
+
+fn binary() -> io::Result<path::PathBuf> {
+    Ok(std::env::current_exe()?.with_file_name(BINARY_NAME))
+}
+
+fn is_alive(pid: libc::pid_t) -> bool {
+    unsafe { libc::kill(pid, 0) == 0 }
+}
+
+/// The pid of the running daemon, if there is one.
+pub fn running_pid(config: &config::FullConfig) -> Option<libc::pid_t> {
+    let pid = fs::read_to_string(pidfile_path(config)).ok()?.trim().parse().ok()?;
+    is_alive(pid).then_some(pid)
+}
+
+fn wait_until(condition: impl Fn() -> bool) -> bool {
+    let started = time::Instant::now();
+    while started.elapsed() < STARTUP_TIMEOUT {
+        if condition() {
+            return true;
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+    condition()
+}
+
+/// Asks for the passphrase of an encrypted history now, and checks it, rather than leaving it to a
+/// daemon without a terminal.
+fn unlock(config: &config::FullConfig) -> Result<(), HistoryError> {
+    if encryption::needs_passphrase(&config.history) && encryption::passphrase().is_none() {
+        encryption::open_history(&config.history, &config.core.data_dir.join(&config.history.history_fp))?;
+    }
+    Ok(())
+}
+
+/// Takes the passphrase handed over by `spawn_passing_passphrase` on stdin.
+fn read_passphrase_from_stdin(config: &config::FullConfig) -> Result<(), HistoryError> {
+    use std::io::{BufRead, IsTerminal};
+    if !encryption::needs_passphrase(&config.history) || io::stdin().is_terminal() {
+        return Ok(());
+    }
+    let mut passphrase = String::new();
+    io::stdin().lock().read_line(&mut passphrase)?;
+    let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+    if !passphrase.is_empty() {
+        encryption::set_passphrase(passphrase.to_string());
+    }
+    Ok(())
+}
+
+/// The passphrase is written to the child's stdin, as arguments and the environment can be read
+/// by other users.
+fn spawn_passing_passphrase(command: &mut process::Command) -> Result<process::Child, HistoryError> {
+    use std::io::Write;
+    let passphrase = encryption::passphrase();
+    if passphrase.is_some() {
+        command.stdin(process::Stdio::piped());
+    }
+    let mut child = command.spawn()?;
+    if let (Some(passphrase), Some(mut stdin)) = (passphrase, child.stdin.take()) {
+        writeln!(stdin, "{}", passphrase)?;
+    }
+    Ok(child)
+}
+
+/// Serves the history until `SIGTERM` or `SIGINT` is received.
+pub fn run(config: &config::FullConfig) -> Result<(), HistoryError> {
+    // Blocked before any threads are spawned so that they all inherit the mask, leaving the signals
+    // to the `sigwait` below.
+    let signals = unsafe {
+        let mut signals: libc::sigset_t = std::mem::zeroed();
+        libc::sigemptyset(&mut signals);
+        libc::sigaddset(&mut signals, libc::SIGTERM);
+        libc::sigaddset(&mut signals, libc::SIGINT);
+        libc::pthread_sigmask(libc::SIG_BLOCK, &signals, std::ptr::null_mut());
+        signals
+    };
+
+    read_passphrase_from_stdin(config)?;
+    let listener = data_layer::bind(config)?;
+    let history = Arc::new(RwLock::new(data_layer::DataLayerHistory::init(config)?));
+
+    let pidfile = pidfile_path(config);
+    {
+        use std::io::Write;
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&pidfile)?;
+        writeln!(file, "{}", process::id())?;
+    }
+
+    {
+        let history = history.clone();
+        thread::spawn(move || data_layer::serve(listener, history));
+    }
+
+    let mut signal = 0;
+    unsafe { libc::sigwait(&signals, &mut signal) };
+
+    // Holding the lock keeps any further entries from being written while shutting down
+    let history = history.write().map_err(|_| HistoryError::Poisoned)?;
+    history.flush()?;
+    let _ = fs::remove_file(protocol::socket_path(config));
+    let _ = fs::remove_file(pidfile);
+    Ok(())
+}
+
+/// Starts `run` in a detached process and waits for it to accept connections.
+/// Returns the pid of the daemon, which may have already been running.
+pub fn start(config: &config::FullConfig) -> Result<libc::pid_t, HistoryError> {
+    if let Some(pid) = running_pid(config) {
+        return Ok(pid);
+    }
+    read_passphrase_from_stdin(config)?;
+    unlock(config)?;
+
+    let mut command = process::Command::new(binary()?);
+    command
+        .arg("run")
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null());
+    unsafe {
+        command.pre_exec(|| {
+            // Leave the terminal's session so that e.g. closing it doesn't take the daemon along
+            libc::setsid();
+            Ok(())
+        });
+    }
+    spawn_passing_passphrase(&mut command)?;
+
+    if !wait_until(|| data_layer::connect(config).is_ok()) {
+        return Err(HistoryError::Io(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the history daemon did not start",
+        )));
+    }
+    running_pid(config).ok_or(HistoryError::UnexpectedResponse)
+}
+
+/// Sends `SIGTERM` to the daemon and waits for it to exit.
+/// Returns the pid of the daemon that was stopped, if one was running.
+pub fn stop(config: &config::FullConfig) -> Result<Option<libc::pid_t>, HistoryError> {
+    let pid = match running_pid(config) {
+        Some(pid) => pid,
+        None => return Ok(None),
+    };
+    if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    if !wait_until(|| !is_alive(pid)) {
+        return Err(HistoryError::Io(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "the history daemon did not exit",
+        )));
+    }
+    Ok(Some(pid))
+}
+
+/// Number of entries held by the running daemon.
+pub fn status(config: &config::FullConfig) -> Result<usize, HistoryError> {
+    let mut stream = data_layer::connect(config)?;
+    protocol::write_message(&mut stream, &HistoryRequest::GetNumHistoryEntries)?;
+    match protocol::read_message(&mut stream)? {
+        Some(HistoryResponse::HistoryLen { len, .. }) => Ok(len),
+        _ => Err(HistoryError::UnexpectedResponse),
+    }
+}
+
+/// Used by shells to start the daemon on demand, through `whale-historyd start` so that the
+/// daemon isn't left as a child of the shell.
+pub fn spawn(config: &config::FullConfig) -> Result<(), HistoryError> {
+    unlock(config)?;
+    let mut command = process::Command::new(binary()?);
+    command
+        .arg("start")
+        .stdin(process::Stdio::null())
+        .stdout(process::Stdio::null())
+        .stderr(process::Stdio::null());
+    let status = spawn_passing_passphrase(&mut command)?.wait()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(HistoryError::Server(format!("{} start exited with {}", BINARY_NAME, status))),
+    }
+}
@@ -1,190 +1,435 @@
-/// This module is a "backend" to multiple processes.
-/// IPC is done via a TCP connection.
-/// Multiple processes of whale_rs can connect to this TCP server.
-/// The TCP server can be run as a daemon // TODO
-/// Or the TCP server is started by the first process that finds there isn't a TCP server running.
-/// If the owning process dies, the TCP server will die with it, and another instance can start one.
+//! This module is a "backend" to multiple processes.
+//! IPC is done over a Unix domain socket in a per-user runtime directory, see `protocol`.
+//! Multiple processes of whale_rs can connect to this socket.
+//! The server is normally the `whale-historyd` daemon (see `daemon`), which the first process that
+//! finds there isn't a server running starts.
+//! If that fails, the server is run on a thread of that process instead. If the owning process dies,
+//! the server will die with it, and another instance can start one.
+//! Where sockets aren't available, or the server speaks a different protocol version, clients keep
+//! the history in process instead.
 
 use std::{fs, io::{self, Write}, path, thread, time};
-use std::cell::RefCell;
-use std::io::Read;
-use std::net::TcpListener;
-use std::rc::Rc;
+use std::borrow::Cow;
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
 use std::sync::{Arc, RwLock};
 use serde::{Deserialize, Serialize};
-use crate::history::BUFFER_SIZE;
-use crate::state;
-use super::HistoryEntry;
+use crate::{config, fuzzy};
+use super::{encryption, usage, HistoryEntry, HistoryFilter, HistoryScope};
+use super::protocol::{self, HistoryError};
+use super::retention::RetentionPolicy;
+use super::stats::{self, HistoryStats};
 
 
-#[derive(Serialize, Deserialize)]
+#[derive(Clone, Serialize, Deserialize)]
 pub enum HistoryRequest {
+    /// Must stay the first variant, see `protocol`.
+    Hello {
+        version: u32,
+    },
     Exit,
-    AddToHistory(String),
+    AddToHistory(HistoryEntry),
     FindFirstOccurrence(String),
+    /// Entries read from another shell's history, see `import`.
+    Import(Vec<HistoryEntry>),
+    /// The newest `limit` entries matching the filter, with their indices, oldest first.
+    Query {
+        filter: HistoryFilter,
+        limit: Option<usize>,
+    },
+    /// Removes the entries in each inclusive range of indices. If `generation` is given and the
+    /// indices have changed since, nothing is removed.
+    Delete {
+        generation: Option<u64>,
+        ranges: Vec<(usize, usize)>,
+    },
+    /// Applies the retention policy now, rather than when the history next outgrows it.
+    Compact,
+    /// Usage statistics over the entries in `scope`, keeping the `top` most used of each kind.
+    Stats {
+        scope: HistoryScope,
+        top: usize,
+    },
+    /// How often and how recently each executable was run, or with `Some(executable)`, each word
+    /// passed to it, see `usage::compute`.
+    Usage {
+        executable: Option<String>,
+    },
     GetHistoryInd(usize),
     GetNumHistoryEntries,
+    /// The closest entry before index `from` whose command matches `query`, skipping any whose
+    /// command is `skip`. If the indices changed since `generation`, starts from the newest entry.
+    FindOlder {
+        query: String,
+        mode: fuzzy::MatchMode,
+        scope: HistoryScope,
+        generation: u64,
+        from: usize,
+        skip: Option<String>,
+    },
+    /// The closest entry after index `from` whose command matches `query`, skipping any whose
+    /// command is `skip`. If the indices changed since `generation`, nothing is found.
+    FindNewer {
+        query: String,
+        mode: fuzzy::MatchMode,
+        scope: HistoryScope,
+        generation: u64,
+        from: usize,
+        skip: Option<String>,
+    },
+    /// Matches of `query` against every unique command, best first. Only `limit` matches starting
+    /// from `offset` are returned, so only what is on screen is sent.
+    Search {
+        query: String,
+        mode: fuzzy::MatchMode,
+        scope: HistoryScope,
+        offset: usize,
+        limit: usize,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SearchMatch {
+    pub entry: HistoryEntry,
+    /// `char` indices of the matched characters in the command.
+    pub positions: Vec<usize>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum HistoryResponse {
+    /// Must stay the first variant, see `protocol`.
+    Hello {
+        version: u32,
+    },
     HistoryVal(Option<HistoryEntry>),
-    HistoryInd(usize),
+    /// `generation` changes whenever the indices of existing entries do, e.g. after compaction.
+    HistoryLen {
+        len: usize,
+        generation: u64,
+    },
+    IndexedHistoryVal {
+        generation: u64,
+        found: Option<(usize, HistoryEntry)>,
+    },
+    SearchResults {
+        total: usize,
+        matches: Vec<SearchMatch>,
+    },
+    Imported {
+        added: usize,
+        skipped: usize,
+    },
+    Entries {
+        generation: u64,
+        entries: Vec<(usize, HistoryEntry)>,
+    },
+    Deleted(usize),
+    Compacted(usize),
+    Stats(HistoryStats),
+    Usage(HashMap<String, f64>),
     Ok,
+    Error(String),
 }
 
-pub fn start_history_data_layer(program_state: Rc<RefCell<state::ProgramState>>) -> anyhow::Result<()> {
-    let (listener, history) = {
-        let p_state = program_state.borrow();
-        let port = p_state.config.history.tcp_port;
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
-        let history = Arc::new(RwLock::new(DataLayerHistory::init(program_state.clone())));
-        (listener, history)
-    };
+pub type SharedHistory = Arc<RwLock<DataLayerHistory>>;
 
-    thread::spawn(move || {
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let history = history.clone();
-                    thread::spawn(move || {
-                        handle_request(stream, history)
-                    });
-                }
-                Err(_e) => (),
+/// Binds the history socket, replacing one left behind by a server that is no longer running.
+#[cfg(unix)]
+pub fn bind(config: &config::FullConfig) -> Result<UnixListener, HistoryError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let socket_path = protocol::socket_path(config);
+    if config.history.socket_path.is_none() {
+        if let Some(runtime_dir) = socket_path.parent() {
+            protocol::ensure_runtime_dir(runtime_dir)?;
+        }
+    }
+    if socket_path.exists() {
+        if UnixStream::connect(&socket_path).is_ok() {
+            return Err(HistoryError::Io(io::ErrorKind::AddrInUse.into()));
+        }
+        fs::remove_file(&socket_path)?;
+    }
+    let listener = UnixListener::bind(&socket_path)?;
+    fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+    Ok(listener)
+}
+
+/// Accepts connections until the listener fails, serving each on its own thread.
+#[cfg(unix)]
+pub fn serve(listener: UnixListener, history: SharedHistory) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let history = history.clone();
+                thread::spawn(move || {
+                    // A broken connection only affects the client on the other end
+                    let _ = handle_connection(stream, history);
+                });
             }
+            Err(_e) => (),
         }
-    });
+    }
+}
 
+/// Connects to the socket and checks that the data layer speaks the same protocol version.
+#[cfg(unix)]
+pub fn connect(config: &config::FullConfig) -> Result<UnixStream, HistoryError> {
+    let mut stream = UnixStream::connect(protocol::socket_path(config))?;
+    protocol::write_message(&mut stream, &HistoryRequest::Hello { version: protocol::PROTOCOL_VERSION })?;
+    match protocol::read_message(&mut stream)? {
+        Some(HistoryResponse::Hello { version }) if version == protocol::PROTOCOL_VERSION => Ok(stream),
+        Some(HistoryResponse::Hello { version }) => Err(HistoryError::VersionMismatch {
+            client: protocol::PROTOCOL_VERSION,
+            server: version,
+        }),
+        _ => Err(HistoryError::UnexpectedResponse),
+    }
+}
+
+#[cfg(unix)]
+pub fn start_history_data_layer(config: &config::FullConfig) -> Result<(), HistoryError> {
+    let listener = bind(config)?;
+    let history = Arc::new(RwLock::new(DataLayerHistory::init(config)?));
+    thread::spawn(move || serve(listener, history));
     Ok(())
 }
 
-fn handle_request(stream: std::net::TcpStream, history: Arc<RwLock<DataLayerHistory>>) {
-    let mut stream = stream;
-    // Buffer is 2**13 for typing commands, so this should be fine for now.
-    let mut buffer = [0; BUFFER_SIZE];
-    loop {
-        let bytes = stream.read(&mut buffer).unwrap();
-        if bytes == 0 {
-            break;
-        }
-        let request: HistoryRequest = bincode::deserialize(&buffer[..bytes]).unwrap();
-        let mut exit = false;
-        let resp = match request {
-            HistoryRequest::Exit => {
-                exit = true;
-                HistoryResponse::Ok
-            }
-            HistoryRequest::AddToHistory(command) => {
-                let mut history = history.write().unwrap();
-                history.add_to_history(&command).unwrap();
-                HistoryResponse::Ok
-            }
-            HistoryRequest::FindFirstOccurrence(command) => {
-                let history = history.read().unwrap();
-                let history_entry = history.find_first(&command);
-                HistoryResponse::HistoryVal(history_entry.cloned())
-            }
-            HistoryRequest::GetNumHistoryEntries => {
-                let history = history.read().unwrap();
-                HistoryResponse::HistoryInd(history.len())
-            }
-            HistoryRequest::GetHistoryInd(ind) => {
-                let mut history = history.write().unwrap();
-                HistoryResponse::HistoryVal(history.get_history(ind).cloned())
-            }
+#[cfg(unix)]
+fn handle_connection(mut stream: UnixStream, history: SharedHistory) -> Result<(), HistoryError> {
+    while let Some(request) = protocol::read_message::<_, HistoryRequest>(&mut stream)? {
+        let exit = match &request {
+            HistoryRequest::Hello { version } => *version != protocol::PROTOCOL_VERSION,
+            HistoryRequest::Exit => true,
+            _ => false,
         };
-        let resp = bincode::serialize(&resp).unwrap();
-        stream.write(&resp).unwrap();
-
+        protocol::write_message(&mut stream, &respond(&history, request))?;
         if exit {
             break;
         }
     }
+    Ok(())
+}
+
+/// Used by the socket server, and directly by clients that keep the history in process.
+pub fn respond(history: &RwLock<DataLayerHistory>, request: HistoryRequest) -> HistoryResponse {
+    match handle_request(history, request) {
+        Ok(resp) => resp,
+        Err(e) => HistoryResponse::Error(e.to_string()),
+    }
+}
+
+fn handle_request(
+    history: &RwLock<DataLayerHistory>,
+    request: HistoryRequest,
+) -> Result<HistoryResponse, HistoryError> {
+    let read = || history.read().map_err(|_| HistoryError::Poisoned);
+    let write = || history.write().map_err(|_| HistoryError::Poisoned);
+
+    let resp = match request {
+        HistoryRequest::Hello { .. } => HistoryResponse::Hello { version: protocol::PROTOCOL_VERSION },
+        HistoryRequest::Exit => HistoryResponse::Ok,
+        HistoryRequest::AddToHistory(history_entry) => {
+            write()?.add_to_history(history_entry)?;
+            HistoryResponse::Ok
+        }
+        HistoryRequest::Import(history_entries) => {
+            let (added, skipped) = write()?.import(history_entries)?;
+            HistoryResponse::Imported { added, skipped }
+        }
+        HistoryRequest::Query { filter, limit } => {
+            let history = read()?;
+            HistoryResponse::Entries { generation: history.generation, entries: history.query(&filter, limit) }
+        }
+        HistoryRequest::Delete { generation, ranges } => {
+            let mut history = write()?;
+            if generation.is_some_and(|generation| generation != history.generation) {
+                return Err(HistoryError::StaleIndices);
+            }
+            HistoryResponse::Deleted(history.delete(&ranges)?)
+        }
+        HistoryRequest::Compact => HistoryResponse::Compacted(write()?.compact()?),
+        HistoryRequest::Stats { scope, top } => {
+            let history = read()?;
+            let stats = match history.scope_indices(&scope) {
+                None => stats::compute(history.history.iter(), top),
+                Some(indices) => stats::compute(indices.iter().map(|i| &history.history[*i]), top),
+            };
+            HistoryResponse::Stats(stats)
+        }
+        HistoryRequest::Usage { executable } => {
+            let history = read()?;
+            HistoryResponse::Usage(usage::compute(history.history.iter(), executable.as_deref(), now().as_secs()))
+        }
+        HistoryRequest::FindFirstOccurrence(command) => {
+            HistoryResponse::HistoryVal(read()?.find_first(&command).cloned())
+        }
+        HistoryRequest::GetNumHistoryEntries => {
+            let history = read()?;
+            HistoryResponse::HistoryLen { len: history.len(), generation: history.generation }
+        }
+        HistoryRequest::GetHistoryInd(ind) => {
+            HistoryResponse::HistoryVal(read()?.get_history(ind).cloned())
+        }
+        HistoryRequest::FindOlder { query, mode, scope, generation, from, skip } => {
+            let history = read()?;
+            let from = if generation == history.generation { from } else { history.len() };
+            let indices = history.scope_indices(&scope);
+            let found = match &indices {
+                None => history.find_matching(&query, mode, skip.as_deref(), (0..from).rev()),
+                Some(indices) => {
+                    let end = indices.partition_point(|i| *i < from);
+                    history.find_matching(&query, mode, skip.as_deref(), indices[..end].iter().rev().copied())
+                }
+            };
+            HistoryResponse::IndexedHistoryVal { generation: history.generation, found }
+        }
+        HistoryRequest::FindNewer { query, mode, scope, generation, from, skip } => {
+            let history = read()?;
+            let indices = history.scope_indices(&scope);
+            let found = match (generation == history.generation, &indices) {
+                (false, _) => None,
+                (true, None) => history.find_matching(&query, mode, skip.as_deref(), from + 1..history.len()),
+                (true, Some(indices)) => {
+                    let start = indices.partition_point(|i| *i <= from);
+                    history.find_matching(&query, mode, skip.as_deref(), indices[start..].iter().copied())
+                }
+            };
+            HistoryResponse::IndexedHistoryVal { generation: history.generation, found }
+        }
+        HistoryRequest::Search { query, mode, scope, offset, limit } => {
+            let (total, matches) = read()?.search(&query, mode, &scope, offset, limit);
+            HistoryResponse::SearchResults { total, matches }
+        }
+    };
+    Ok(resp)
 }
 
 #[derive(Debug)]
-struct DataLayerHistory {
+pub struct DataLayerHistory {
+    history_fp: path::PathBuf,
     history_file: fs::File,
+    /// Bytes in the history file, kept up to date as entries are appended.
+    file_len: u64,
     history: Vec<HistoryEntry>,
+    /// Indices of the entries of each session, and run in each directory, in ascending order.
+    by_session: HashMap<String, Vec<usize>>,
+    by_cwd: HashMap<path::PathBuf, Vec<usize>>,
+    retention: RetentionPolicy,
+    /// Changes whenever existing entries move to other indices. It starts at the time the history
+    /// was read, so that clients of a server that restarted don't mistake its indices for the old.
+    generation: u64,
+    /// `None` if the file is kept in plain text.
+    cipher: Option<encryption::Cipher>,
 }
 
-impl DataLayerHistory {
-    fn init(program_state: Rc<RefCell<state::ProgramState>>) -> Self {
-        let p_state = program_state.borrow();
+fn now() -> time::Duration {
+    time::SystemTime::now().duration_since(time::UNIX_EPOCH).unwrap_or_default()
+}
 
-        let history_config = &p_state.config.history;
-        let history_fp = p_state.config.core.data_dir.join(&history_config.history_fp);
+impl DataLayerHistory {
+    pub fn init(config: &config::FullConfig) -> Result<Self, HistoryError> {
+        let history_config = &config.history;
+        let history_fp = config.core.data_dir.join(&history_config.history_fp);
 
-        let history = Self::parse_history_file(&history_fp).unwrap();
-        let history = Self::reduce_history_file(
-            &history_fp, history_config.max_file_size_bytes, history).unwrap();
-        let history_file = crate::utils::appendable_file(&history_fp).unwrap();
-        Self {
+        let (cipher, needs_rewrite) = encryption::open_history(history_config, &history_fp)?;
+        // A file that still has to be encrypted is read as plain text
+        let history = Self::parse_history_file(&history_fp, cipher.as_ref().filter(|_| !needs_rewrite))?;
+        let history_file = crate::utils::appendable_file(&history_fp)?;
+        let mut data_layer = Self {
+            file_len: fs::metadata(&history_fp)?.len(),
+            history_fp,
             history_file,
             history,
+            by_session: HashMap::new(),
+            by_cwd: HashMap::new(),
+            retention: RetentionPolicy::from_config(history_config),
+            generation: now().as_nanos() as u64,
+            cipher,
+        };
+        data_layer.reindex();
+        if needs_rewrite {
+            // Encrypts a plain text history, or starts a new one with the header
+            data_layer.rewrite_history_file()?;
         }
+
+        let oldest = now().as_secs().saturating_sub(data_layer.retention.max_age);
+        let has_expired = data_layer.retention.max_age != 0
+            && data_layer.history.first().is_some_and(|history_entry| history_entry.timestamp < oldest);
+        if has_expired || data_layer.exceeds_retention() {
+            data_layer.compact()?;
+        }
+        Ok(data_layer)
     }
 
     fn len(&self) -> usize {
         self.history.len()
     }
 
-    /// This function is expensive.
-    /// This should be fine, since it will only run when the history file size has been exceeded.
-    fn reduce_history_file(
-        history_fp: &path::PathBuf,
-        max_file_size_bytes: u64,
-        history: Vec<HistoryEntry>,
-    ) -> Result<Vec<HistoryEntry>, io::Error> {
-        if !history_fp.exists() {
-            panic!("Function called in bad order.")
-        }
+    /// Makes sure everything written so far has reached the disk.
+    pub fn flush(&self) -> Result<(), HistoryError> {
+        self.history_file.sync_all()?;
+        Ok(())
+    }
 
-        if fs::metadata(&history_fp)?.len() < max_file_size_bytes {
-            return Ok(history);
+    fn index_entry(&mut self, i: usize) {
+        let history_entry = &self.history[i];
+        if let Some(session_id) = &history_entry.session_id {
+            self.by_session.entry(session_id.clone()).or_default().push(i);
         }
-
-        let mut entries_to_remove = vec![];
-        for (i, history_entry) in history.iter().enumerate().rev().skip(1) {
-            let mut remove = false;
-            for already_entered in history[i + 1..history.len()].iter() {
-                if history_entry.command == already_entered.command {
-                    remove = true;
-                    break;
-                }
-            }
-            if remove {
-                entries_to_remove.push(i);
-            }
+        if let Some(cwd) = &history_entry.cwd {
+            self.by_cwd.entry(cwd.clone()).or_default().push(i);
         }
+    }
 
-        let temp_file_path = format!("{}.temp", history_fp.to_str().unwrap());
-        let mut temp_file = fs::OpenOptions::new()
-            .read(false)
-            .append(true)
-            .create(true)
-            .open(&temp_file_path)?;
+    /// Rebuilds `by_session` and `by_cwd`, for when entries moved.
+    fn reindex(&mut self) {
+        self.by_session.clear();
+        self.by_cwd.clear();
+        for i in 0..self.history.len() {
+            self.index_entry(i);
+        }
+    }
 
-        let mut removal_counter = entries_to_remove.len() - 1;
-        for (i, history_entry) in history.into_iter().enumerate() {
-            if i == entries_to_remove[removal_counter] {
-                if removal_counter > 0 {
-                    removal_counter -= 1;
-                }
-                continue;
+    /// The indices of the entries in `scope` in ascending order, or `None` for all of them.
+    pub fn scope_indices(&self, scope: &HistoryScope) -> Option<Cow<'_, [usize]>> {
+        match scope {
+            HistoryScope::Global => None,
+            HistoryScope::Session(session_id) => Some(match self.by_session.get(session_id) {
+                Some(indices) => Cow::Borrowed(indices.as_slice()),
+                None => Cow::Owned(vec![]),
+            }),
+            HistoryScope::Directory(dir) => {
+                let mut indices = self.by_cwd
+                    .iter()
+                    .filter(|(cwd, _)| cwd.starts_with(dir))
+                    .flat_map(|(_, indices)| indices.iter().copied())
+                    .collect::<Vec<usize>>();
+                indices.sort_unstable();
+                Some(Cow::Owned(indices))
             }
-            temp_file.write(history_entry.to_line().as_bytes())?;
         }
-        drop(temp_file);
+    }
 
-        fs::remove_file(&history_fp)?;
-        fs::rename(&temp_file_path, &history_fp)?;
+    fn exceeds_retention(&self) -> bool {
+        self.retention.exceeded(self.history.len(), self.file_len)
+    }
 
-        Self::parse_history_file(&history_fp)
+    /// Applies the retention policy and rewrites the file, returning how many entries were removed.
+    pub fn compact(&mut self) -> Result<usize, HistoryError> {
+        let before = self.history.len();
+        let history = std::mem::take(&mut self.history);
+        self.history = self.retention.apply(history, now().as_secs());
+        self.rewrite_history_file()?;
+        Ok(before - self.history.len())
     }
 
-    fn parse_history_file(history_fp: &path::PathBuf) -> Result<Vec<HistoryEntry>, io::Error> {
+    /// Lines that can't be decrypted or parsed are skipped.
+    fn parse_history_file(
+        history_fp: &path::PathBuf,
+        cipher: Option<&encryption::Cipher>,
+    ) -> Result<Vec<HistoryEntry>, io::Error> {
         if !history_fp.exists() {
             fs::File::create(history_fp)?;
         }
@@ -192,12 +437,12 @@ impl DataLayerHistory {
         let mut history = crate::utils::read_lines(history_fp)?
             .enumerate()
             .filter_map(|(line_number, line)| {
-                if let Ok(line) = line {
-                    if let Ok(history_entry) = HistoryEntry::from_line(&line, line_number as u32) {
-                        return Some(history_entry);
-                    }
-                }
-                None
+                let line = match (line.ok()?, cipher) {
+                    (line, _) if encryption::is_header(&line) => return None,
+                    (line, Some(cipher)) => cipher.decrypt_line(&line)?,
+                    (line, None) => line,
+                };
+                HistoryEntry::from_line(&line, line_number as u32).ok()
             })
             .collect::<Vec<HistoryEntry>>();
         history.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
@@ -205,25 +450,142 @@ impl DataLayerHistory {
         Ok(history)
     }
 
-    pub fn add_to_history(&mut self, cmd: &str) -> anyhow::Result<()> {
-        if cmd.len() == 0 {
+    pub fn add_to_history(&mut self, history_entry: HistoryEntry) -> Result<(), HistoryError> {
+        if history_entry.command.is_empty() {
             return Ok(());
         }
 
-        let current_timestamp = time::SystemTime::now()
-            .duration_since(time::UNIX_EPOCH)?
-            .as_secs();
+        let line = self.encode(&history_entry);
+        self.history_file.write_all(line.as_bytes())?;
+        self.file_len += line.len() as u64;
+        self.history.push(history_entry);
+        self.index_entry(self.history.len() - 1);
 
-        let history_entry = HistoryEntry::new(current_timestamp, cmd.to_string());
+        if self.exceeds_retention() {
+            self.compact()?;
+        }
+        Ok(())
+    }
 
-        self.history_file.write(history_entry.to_line().as_bytes())?;
-        self.history.push(history_entry);
+    /// Adds the entries that aren't already in the history, returning how many were added and how
+    /// many were skipped. Entries without a timestamp count as present if their command is.
+    pub fn import(&mut self, history_entries: Vec<HistoryEntry>) -> Result<(usize, usize), HistoryError> {
+        let total = history_entries.len();
+        let mut seen = self.history
+            .iter()
+            .map(|history_entry| (history_entry.timestamp, history_entry.command.clone()))
+            .collect::<std::collections::HashSet<(u64, String)>>();
+        let mut seen_commands = self.history
+            .iter()
+            .map(|history_entry| history_entry.command.clone())
+            .collect::<std::collections::HashSet<String>>();
+
+        // Newest first, so that only the last of several untimestamped duplicates is kept
+        let mut added = vec![];
+        for history_entry in history_entries.into_iter().rev() {
+            let is_new = match history_entry.timestamp {
+                0 => seen_commands.insert(history_entry.command.clone()),
+                timestamp => {
+                    seen_commands.insert(history_entry.command.clone());
+                    seen.insert((timestamp, history_entry.command.clone()))
+                }
+            };
+            if is_new && !history_entry.command.is_empty() {
+                added.push(history_entry);
+            }
+        }
 
+        added.reverse();
+        let lines = added.iter().map(|history_entry| self.encode(history_entry)).collect::<String>();
+        self.history_file.write_all(lines.as_bytes())?;
+        self.file_len += lines.len() as u64;
+        let num_added = added.len();
+        self.history.extend(added);
+        // Same order as when the file is read again
+        self.history.sort_by_key(|history_entry| history_entry.timestamp);
+        if num_added > 0 {
+            self.generation += 1;
+            self.reindex();
+        }
+        if self.exceeds_retention() {
+            self.compact()?;
+        }
+
+        Ok((num_added, total - num_added))
+    }
+
+    pub fn query(&self, filter: &HistoryFilter, limit: Option<usize>) -> Vec<(usize, HistoryEntry)> {
+        let mut entries = self.history
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, history_entry)| filter.matches(history_entry))
+            .take(limit.unwrap_or(usize::MAX))
+            .map(|(i, history_entry)| (i, history_entry.clone()))
+            .collect::<Vec<(usize, HistoryEntry)>>();
+        entries.reverse();
+        entries
+    }
+
+    /// Returns how many entries were removed. The file is rewritten from what is left.
+    pub fn delete(&mut self, ranges: &[(usize, usize)]) -> Result<usize, HistoryError> {
+        let in_range = |i: usize| ranges.iter().any(|(start, end)| (*start..=*end).contains(&i));
+        let before = self.history.len();
+        let mut i = 0;
+        self.history.retain(|_| {
+            i += 1;
+            !in_range(i - 1)
+        });
+        let removed = before - self.history.len();
+        if removed > 0 {
+            self.rewrite_history_file()?;
+        }
+        Ok(removed)
+    }
+
+    /// The line `history_entry` is stored as, encrypted if the file is.
+    fn encode(&self, history_entry: &HistoryEntry) -> String {
+        let line = history_entry.to_line();
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt_line(&line),
+            None => line,
+        }
+    }
+
+    /// Replaces the file with the entries in memory. The new contents are written to a temporary
+    /// file which is then renamed over the old one, so a crash leaves either the old or the new
+    /// history and never a mix.
+    fn rewrite_history_file(&mut self) -> Result<(), HistoryError> {
+        let temp_fp = self.history_fp.with_extension(format!("tmp.{}", std::process::id()));
+        self.generation += 1;
+        self.reindex();
+        self.file_len = 0;
+        {
+            let mut temp_file = io::BufWriter::new(fs::File::create(&temp_fp)?);
+            if let Some(cipher) = &self.cipher {
+                let header = cipher.header();
+                temp_file.write_all(header.as_bytes())?;
+                self.file_len += header.len() as u64;
+            }
+            for history_entry in &self.history {
+                let line = self.encode(history_entry);
+                temp_file.write_all(line.as_bytes())?;
+                self.file_len += line.len() as u64;
+            }
+            temp_file.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        fs::rename(&temp_fp, &self.history_fp)?;
+        #[cfg(unix)]
+        if let Some(dir) = self.history_fp.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            // Makes the rename itself durable
+            fs::File::open(dir)?.sync_all()?;
+        }
+        self.history_file = crate::utils::appendable_file(&self.history_fp)?;
         Ok(())
     }
 
     /// None should be interpreted as: "keep whatever is currently in the `Buffer`".
-    pub fn get_history(&mut self, ind: usize) -> Option<&HistoryEntry> {
+    pub fn get_history(&self, ind: usize) -> Option<&HistoryEntry> {
         if self.history.len() == 0 {
             return None;
         } else if ind >= self.history.len() {
@@ -232,12 +594,67 @@ impl DataLayerHistory {
         Some(&self.history[ind])
     }
 
+    /// Redacted entries are skipped, as accepting one would type the placeholder.
     pub fn find_first(&self, cmd: &str) -> Option<&HistoryEntry> {
         for history_entry in self.history.iter().rev() {
-            if history_entry.command.starts_with(cmd) {
+            if !history_entry.redacted && history_entry.command.starts_with(cmd) {
                 return Some(history_entry);
             }
         }
         None
     }
+
+    /// The first entry in `indices` whose command matches `query` and is not `skip`.
+    pub fn find_matching(
+        &self,
+        query: &str,
+        mode: fuzzy::MatchMode,
+        skip: Option<&str>,
+        indices: impl Iterator<Item=usize>,
+    ) -> Option<(usize, HistoryEntry)> {
+        indices
+            .filter(|i| *i < self.history.len())
+            .map(|i| (i, &self.history[i]))
+            .find(|(_, history_entry)| {
+                Some(history_entry.command.as_str()) != skip
+                    && fuzzy::find(mode, query, &history_entry.command).is_some()
+            })
+            .map(|(i, history_entry)| (i, history_entry.clone()))
+    }
+
+    /// Only the most recent occurrence of each command is considered. Matches with equal scores are
+    /// kept newest first.
+    pub fn search(
+        &self,
+        query: &str,
+        mode: fuzzy::MatchMode,
+        scope: &HistoryScope,
+        offset: usize,
+        limit: usize,
+    ) -> (usize, Vec<SearchMatch>) {
+        let mut seen = std::collections::HashSet::new();
+        let entries: Box<dyn Iterator<Item=&HistoryEntry>> = match self.scope_indices(scope) {
+            None => Box::new(self.history.iter().rev()),
+            Some(indices) => Box::new(indices.into_owned().into_iter().rev().map(|i| &self.history[i])),
+        };
+        let mut matches = entries
+            .filter(|history_entry| seen.insert(history_entry.command.as_str()))
+            .filter_map(|history_entry| {
+                fuzzy::find(mode, query, &history_entry.command).map(|m| (m, history_entry))
+            })
+            .collect::<Vec<(fuzzy::Match, &HistoryEntry)>>();
+        matches.sort_by_key(|(m, _)| std::cmp::Reverse(m.score));
+
+        let total = matches.len();
+        let matches = matches
+            .into_iter()
+            .skip(offset)
+            .take(limit)
+            .map(|(m, history_entry)| SearchMatch {
+                entry: history_entry.clone(),
+                positions: m.positions,
+            })
+            .collect();
+        (total, matches)
+    }
 }
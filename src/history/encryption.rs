@@ -0,0 +1,256 @@
+//! Encryption of the history file at rest, turned on with `encrypt` in `ConfigHistory`.
+//!
+//! An encrypted file starts with a header holding the salt the key is derived from, and a check
+//! value that tells a wrong passphrase apart from a corrupt file:
+//! ```text
+//! whale-history-encrypted v1 <salt> <check>
+//! ```
+//! Every line after it is one entry as `HistoryEntry::to_line` writes it, sealed with
+//! XChaCha20-Poly1305 under a random nonce and base64 encoded, so entries can still be appended
+//! one at a time. The salt is authenticated along with every entry, so entries can't be moved
+//! between files.
+
+use std::{fmt, fs, io, path};
+use std::sync::OnceLock;
+use argon2::Argon2;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chacha20poly1305::{AeadCore, KeyInit, XChaCha20Poly1305, XNonce};
+use chacha20poly1305::aead::{Aead, OsRng, Payload};
+use chacha20poly1305::aead::rand_core::RngCore;
+use crate::config;
+use super::protocol::HistoryError;
+
+const HEADER_PREFIX: &str = "whale-history-encrypted v1 ";
+const CHECK_PLAINTEXT: &[u8] = b"whale-history";
+const SALT_LEN: usize = 16;
+const NONCE_LEN: usize = 24;
+const TAG_LEN: usize = 16;
+
+/// The passphrase, once it has been entered or handed over by `whale-historyd start`, so that it
+/// is asked for at most once per process.
+static PASSPHRASE: OnceLock<String> = OnceLock::new();
+
+pub fn set_passphrase(passphrase: String) {
+    let _ = PASSPHRASE.set(passphrase);
+}
+
+pub fn passphrase() -> Option<&'static str> {
+    PASSPHRASE.get().map(String::as_str)
+}
+
+/// Whether a passphrase is needed to open the history, rather than a key file.
+pub fn needs_passphrase(config: &config::history::ConfigHistory) -> bool {
+    config.encrypt && config.key_file.is_none()
+}
+
+pub fn is_header(line: &str) -> bool {
+    line.starts_with(HEADER_PREFIX)
+}
+
+pub struct Cipher {
+    cipher: XChaCha20Poly1305,
+    salt: [u8; SALT_LEN],
+}
+
+impl fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cipher").finish_non_exhaustive()
+    }
+}
+
+impl Cipher {
+    fn derive(secret: &[u8], salt: [u8; SALT_LEN]) -> Result<Self, HistoryError> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(secret, &salt, &mut key)
+            .map_err(|e| HistoryError::Encryption(e.to_string()))?;
+        Ok(Self { cipher: XChaCha20Poly1305::new(&key.into()), salt })
+    }
+
+    fn seal(&self, plaintext: &[u8]) -> String {
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher
+            .encrypt(&nonce, Payload { msg: plaintext, aad: &self.salt })
+            .expect("History entries are far below the cipher's size limit");
+        let mut sealed = nonce.to_vec();
+        sealed.extend(ciphertext);
+        BASE64.encode(sealed)
+    }
+
+    fn open(&self, sealed: &str) -> Option<Vec<u8>> {
+        let sealed = BASE64.decode(sealed.trim()).ok()?;
+        if sealed.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &self.salt })
+            .ok()
+    }
+
+    pub fn header(&self) -> String {
+        format!("{}{} {}\n", HEADER_PREFIX, BASE64.encode(self.salt), self.seal(CHECK_PLAINTEXT))
+    }
+
+    /// Seals a line written by `HistoryEntry::to_line`.
+    pub fn encrypt_line(&self, line: &str) -> String {
+        format!("{}\n", self.seal(line.trim_end_matches('\n').as_bytes()))
+    }
+
+    /// `None` if the line was tampered with, or sealed with another key.
+    pub fn decrypt_line(&self, line: &str) -> Option<String> {
+        String::from_utf8(self.open(line)?).ok()
+    }
+}
+
+/// Length of what `Cipher::encrypt_line` returns for a line of `len` bytes, ending in a newline.
+pub fn encrypted_line_len(len: usize) -> usize {
+    let sealed = NONCE_LEN + len.saturating_sub(1) + TAG_LEN;
+    sealed.div_ceil(3) * 4 + 1
+}
+
+/// Key files hold a passphrase or random bytes, and must not be readable by other users.
+fn read_key_file(key_file: &path::Path) -> Result<Vec<u8>, HistoryError> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if fs::metadata(key_file)?.permissions().mode() & 0o077 != 0 {
+            return Err(HistoryError::Encryption(format!(
+                "key file {} is accessible by other users, restrict it with `chmod 600`",
+                key_file.display()
+            )));
+        }
+    }
+    let mut key = fs::read(key_file)?;
+    while key.last().is_some_and(|b| b.is_ascii_whitespace()) {
+        key.pop();
+    }
+    match key.is_empty() {
+        true => Err(HistoryError::Encryption(format!("key file {} is empty", key_file.display()))),
+        false => Ok(key),
+    }
+}
+
+/// Asks for a line on the controlling terminal without echoing it. Works while the shell has the
+/// terminal in raw mode, since the terminal's settings are restored afterwards.
+#[cfg(unix)]
+pub fn read_passphrase(prompt: &str) -> Result<String, HistoryError> {
+    use std::io::{BufRead, Write};
+    use std::os::unix::io::AsRawFd;
+
+    let tty = fs::OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+    let fd = tty.as_raw_fd();
+    let mut original: libc::termios = unsafe { std::mem::zeroed() };
+    if unsafe { libc::tcgetattr(fd, &mut original) } != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+    let mut silent = original;
+    silent.c_lflag &= !libc::ECHO;
+    silent.c_lflag |= libc::ICANON;
+    silent.c_iflag |= libc::ICRNL;
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &silent) };
+
+    let mut line = String::new();
+    let result = (&tty)
+        .write_all(prompt.as_bytes())
+        .and_then(|_| io::BufReader::new(&tty).read_line(&mut line));
+    unsafe { libc::tcsetattr(fd, libc::TCSANOW, &original) };
+    let _ = (&tty).write_all(b"\r\n");
+    result?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+#[cfg(not(unix))]
+pub fn read_passphrase(_prompt: &str) -> Result<String, HistoryError> {
+    Err(HistoryError::Encryption(String::from("passphrases are only supported on unix, use key_file")))
+}
+
+fn secret(config: &config::history::ConfigHistory) -> Result<Vec<u8>, HistoryError> {
+    if let Some(key_file) = &config.key_file {
+        let key_file = key_file.to_string_lossy();
+        return read_key_file(&crate::utils::resolve_path(&key_file, path::Path::new("/")));
+    }
+    if let Some(passphrase) = passphrase() {
+        return Ok(passphrase.as_bytes().to_vec());
+    }
+    let passphrase = read_passphrase("whale history passphrase: ")?;
+    if passphrase.is_empty() {
+        return Err(HistoryError::Encryption(String::from("the passphrase is empty")));
+    }
+    Ok(passphrase.into_bytes())
+}
+
+/// Keeps an entered passphrase for the rest of the process, once it has been found to be right.
+fn remember(config: &config::history::ConfigHistory, secret: Vec<u8>) {
+    if needs_passphrase(config) && passphrase().is_none() {
+        if let Ok(passphrase) = String::from_utf8(secret) {
+            set_passphrase(passphrase);
+        }
+    }
+}
+
+/// The cipher for the history file at `history_fp`, or `None` if it is kept in plain text. Also
+/// returns whether the file still has to be rewritten encrypted, as it is new or in plain text.
+pub fn open_history(
+    config: &config::history::ConfigHistory,
+    history_fp: &path::Path,
+) -> Result<(Option<Cipher>, bool), HistoryError> {
+    let header = match fs::File::open(history_fp) {
+        Ok(file) => {
+            let mut first_line = String::new();
+            io::BufRead::read_line(&mut io::BufReader::new(file), &mut first_line)?;
+            Some(first_line).filter(|line| is_header(line))
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e.into()),
+    };
+
+    match (config.encrypt, header) {
+        (false, None) => Ok((None, false)),
+        (false, Some(_)) => Err(HistoryError::Encryption(format!(
+            "{} is encrypted, set `encrypt = true` in history.toml",
+            history_fp.display()
+        ))),
+        (true, None) => {
+            let mut salt = [0u8; SALT_LEN];
+            OsRng.fill_bytes(&mut salt);
+            let secret = secret(config)?;
+            let cipher = Cipher::derive(&secret, salt)?;
+            remember(config, secret);
+            Ok((Some(cipher), true))
+        }
+        (true, Some(header)) => {
+            let invalid = || HistoryError::Encryption(String::from("the history file's header is invalid"));
+            let (salt, check) = header[HEADER_PREFIX.len()..].trim().split_once(' ').ok_or_else(invalid)?;
+            let salt = BASE64.decode(salt).ok().and_then(|salt| salt.try_into().ok()).ok_or_else(invalid)?;
+            let secret = secret(config)?;
+            let cipher = Cipher::derive(&secret, salt)?;
+            match cipher.open(check).as_deref() == Some(CHECK_PLAINTEXT) {
+                true => {
+                    remember(config, secret);
+                    Ok((Some(cipher), false))
+                }
+                false => Err(HistoryError::Encryption(String::from("wrong passphrase or key"))),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encrypt_line_round_trip() {
+        let cipher = Cipher::derive(b"passphrase", [7; SALT_LEN]).unwrap();
+        let sealed = cipher.encrypt_line("v2 {\"command\":\"ls\"}\n");
+        assert_ne!(sealed.trim_end(), "v2 {\"command\":\"ls\"}");
+        assert_ne!(sealed.trim_end(), BASE64.encode("v2 {\"command\":\"ls\"}"));
+        assert_eq!(cipher.decrypt_line(&sealed).as_deref(), Some("v2 {\"command\":\"ls\"}"));
+        assert_eq!(sealed.len(), encrypted_line_len("v2 {\"command\":\"ls\"}\n".len()));
+
+        let other = Cipher::derive(b"passphrase", [8; SALT_LEN]).unwrap();
+        assert_eq!(other.decrypt_line(&sealed), None);
+    }
+}
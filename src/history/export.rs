@@ -0,0 +1,93 @@
+//! Writes history entries in formats other tools can read.
+
+use std::str::FromStr;
+use super::HistoryEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// One JSON object per line, with every field of the entry.
+    JsonLines,
+    Csv,
+    /// `#<timestamp>` before every command, as bash writes with `HISTTIMEFORMAT` set.
+    Bash,
+}
+
+impl FromStr for ExportFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" | "json" => Ok(Self::JsonLines),
+            "csv" => Ok(Self::Csv),
+            "bash" => Ok(Self::Bash),
+            _ => Err(anyhow::anyhow!("Unknown format `{}`, expected jsonl, csv or bash", s)),
+        }
+    }
+}
+
+const CSV_HEADER: &str = "timestamp,command,exit_status,duration_ms,cwd,hostname,session_id\n";
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn csv_line(entry: &HistoryEntry) -> String {
+    let or_empty = |field: Option<String>| field.unwrap_or_default();
+    let fields = [
+        entry.timestamp.to_string(),
+        csv_field(&entry.command),
+        or_empty(entry.exit_status.map(|status| status.to_string())),
+        or_empty(entry.timing.map(|timing| timing.wall.as_millis().to_string())),
+        csv_field(&or_empty(entry.cwd.as_ref().map(|cwd| cwd.display().to_string()))),
+        csv_field(&or_empty(entry.hostname.clone())),
+        csv_field(&or_empty(entry.session_id.clone())),
+    ];
+    format!("{}\n", fields.join(","))
+}
+
+pub fn export(format: ExportFormat, entries: &[HistoryEntry]) -> String {
+    match format {
+        ExportFormat::JsonLines => entries
+            .iter()
+            .map(|entry| format!("{}\n", serde_json::to_string(entry).unwrap()))
+            .collect(),
+        ExportFormat::Csv => {
+            let lines = entries.iter().map(csv_line).collect::<String>();
+            format!("{}{}", CSV_HEADER, lines)
+        }
+        ExportFormat::Bash => entries
+            .iter()
+            .map(|entry| format!("#{}\n{}\n", entry.timestamp, entry.command))
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_csv_quoting() {
+        let mut entry = HistoryEntry::new(1697089645, "echo \"a, b\"".to_string());
+        entry.exit_status = Some(0);
+        let csv = export(ExportFormat::Csv, &[entry]);
+        assert_eq!(csv.lines().nth(1), Some("1697089645,\"echo \"\"a, b\"\"\",0,,,,"));
+    }
+
+    #[test]
+    fn test_export_bash_round_trip() {
+        let entries = vec![
+            HistoryEntry::new(1697089645, "ls".to_string()),
+            HistoryEntry::new(1697089650, "for i in 1 2; do\necho $i\ndone".to_string()),
+        ];
+        let exported = export(ExportFormat::Bash, &entries);
+        let imported = super::super::import::parse_bash(&exported);
+        assert_eq!(imported.len(), 2);
+        assert_eq!(imported[1].command(), entries[1].command());
+        assert_eq!(imported[1].timestamp(), 1697089650);
+    }
+}
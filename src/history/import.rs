@@ -0,0 +1,234 @@
+//! Reads the history files of other shells into `HistoryEntry`s.
+//! Entries without a timestamp get `0`, which keeps them in file order before everything else.
+
+use std::{path, str::FromStr};
+use super::HistoryEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+impl FromStr for Source {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bash" => Ok(Self::Bash),
+            "zsh" => Ok(Self::Zsh),
+            "fish" => Ok(Self::Fish),
+            _ => Err(anyhow::anyhow!("Unknown shell `{}`, expected bash, zsh or fish", s)),
+        }
+    }
+}
+
+impl Source {
+    /// Where the shell keeps its history by default.
+    pub fn default_path(&self, home: &path::Path) -> path::PathBuf {
+        match self {
+            Self::Bash => home.join(".bash_history"),
+            Self::Zsh => home.join(".zsh_history"),
+            Self::Fish => match std::env::var_os("XDG_DATA_HOME") {
+                Some(data_home) if !data_home.is_empty() => {
+                    path::PathBuf::from(data_home).join("fish").join("fish_history")
+                }
+                _ => home.join(".local").join("share").join("fish").join("fish_history"),
+            },
+        }
+    }
+
+    pub fn parse(&self, contents: &[u8]) -> Vec<HistoryEntry> {
+        match self {
+            Self::Bash => parse_bash(&String::from_utf8_lossy(contents)),
+            Self::Zsh => parse_zsh(&String::from_utf8_lossy(&unmetafy(contents))),
+            Self::Fish => parse_fish(&String::from_utf8_lossy(contents)),
+        }
+    }
+}
+
+fn push_command(entries: &mut Vec<HistoryEntry>, timestamp: u64, command: &str) {
+    let command = command.trim_end_matches('\n');
+    if !command.trim().is_empty() {
+        entries.push(HistoryEntry::new(timestamp, command.to_string()));
+    }
+}
+
+/// Plain lines, or with `HISTTIMEFORMAT` set, a `#<timestamp>` line before every command. In the
+/// latter case everything up to the next timestamp belongs to the same (multi-line) command.
+pub fn parse_bash(contents: &str) -> Vec<HistoryEntry> {
+    let timestamp_of = |line: &str| {
+        line.strip_prefix('#')
+            .filter(|ts| !ts.is_empty() && ts.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|ts| ts.parse::<u64>().ok())
+    };
+    let has_timestamps = contents.lines().any(|line| timestamp_of(line).is_some());
+
+    let mut entries = vec![];
+    if !has_timestamps {
+        for line in contents.lines() {
+            push_command(&mut entries, 0, line);
+        }
+        return entries;
+    }
+
+    let mut timestamp = 0;
+    let mut command = String::new();
+    for line in contents.lines() {
+        match timestamp_of(line) {
+            Some(ts) => {
+                push_command(&mut entries, timestamp, &command);
+                command.clear();
+                timestamp = ts;
+            }
+            None => {
+                command.push_str(line);
+                command.push('\n');
+            }
+        }
+    }
+    push_command(&mut entries, timestamp, &command);
+    entries
+}
+
+/// zsh writes bytes `0x83..=0xa2` (and NUL) as `0x83` followed by the byte xor `0x20`.
+pub fn unmetafy(contents: &[u8]) -> Vec<u8> {
+    const META: u8 = 0x83;
+    let mut bytes = Vec::with_capacity(contents.len());
+    let mut iter = contents.iter();
+    while let Some(b) = iter.next() {
+        match *b {
+            META => match iter.next() {
+                Some(next) => bytes.push(next ^ 0x20),
+                None => break,
+            },
+            b => bytes.push(b),
+        }
+    }
+    bytes
+}
+
+/// `: <timestamp>:<duration>;<command>` with `EXTENDED_HISTORY`, otherwise just the command.
+/// Lines ending in a backslash continue onto the next line.
+pub fn parse_zsh(contents: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = vec![];
+    let mut continued = false;
+    for line in contents.lines() {
+        if continued {
+            if let Some(last) = entries.last_mut() {
+                last.command.push('\n');
+                last.command.push_str(line.strip_suffix('\\').unwrap_or(line));
+            }
+            continued = line.ends_with('\\');
+            continue;
+        }
+
+        let extended = line
+            .strip_prefix(": ")
+            .and_then(|rest| rest.split_once(';'))
+            .and_then(|(meta, command)| {
+                let (timestamp, duration) = meta.split_once(':')?;
+                Some((timestamp.trim().parse::<u64>().ok()?, duration.trim().parse::<u64>().ok()?, command))
+            });
+        let (timestamp, duration, command) = extended.unwrap_or((0, 0, line));
+
+        continued = command.ends_with('\\');
+        let command = command.strip_suffix('\\').unwrap_or(command);
+        if command.trim().is_empty() && !continued {
+            continue;
+        }
+        let mut entry = HistoryEntry::new(timestamp, command.to_string());
+        if extended.is_some() {
+            entry.timing = Some(crate::execution::CommandTiming {
+                wall: std::time::Duration::from_secs(duration),
+                cpu: std::time::Duration::ZERO,
+            });
+        }
+        entries.push(entry);
+    }
+    entries.retain(|entry| !entry.command.trim().is_empty());
+    entries
+}
+
+fn unescape_fish(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some('\\')) => {
+                unescaped.push('\\');
+                chars.next();
+            }
+            _ => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// fish's YAML-like format, of which only `cmd` and `when` are used:
+/// ```text
+/// - cmd: git commit -m "a\nb"
+///   when: 1697089645
+///   paths:
+///     - src
+/// ```
+pub fn parse_fish(contents: &str) -> Vec<HistoryEntry> {
+    let mut entries: Vec<HistoryEntry> = vec![];
+    for line in contents.lines() {
+        if let Some(command) = line.strip_prefix("- cmd: ") {
+            entries.push(HistoryEntry::new(0, unescape_fish(command)));
+        } else if let Some(when) = line.trim_start().strip_prefix("when: ") {
+            if let (Some(entry), Ok(timestamp)) = (entries.last_mut(), when.trim().parse()) {
+                entry.timestamp = timestamp;
+            }
+        }
+    }
+    entries.retain(|entry| !entry.command.trim().is_empty());
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bash() {
+        let plain = parse_bash("ls\n\ncd /tmp\n");
+        assert_eq!(plain.len(), 2);
+        assert_eq!(plain[1].command(), "cd /tmp");
+        assert_eq!(plain[1].timestamp(), 0);
+
+        let timestamped = parse_bash("#1697089645\nls -la\n#1697089650\nfor i in 1 2; do\necho $i\ndone\n");
+        assert_eq!(timestamped.len(), 2);
+        assert_eq!(timestamped[0].timestamp(), 1697089645);
+        assert_eq!(timestamped[1].command(), "for i in 1 2; do\necho $i\ndone");
+    }
+
+    #[test]
+    fn test_parse_zsh() {
+        let mut contents = b": 1697089645:3;cargo build\n: 1697089650:0;echo a\\\nb\n".to_vec();
+        // `—` is 0xe2 0x80 0x94, zsh metafies the 0x94 byte
+        contents.extend_from_slice(b": 1697089655:0;echo \xe2\x80\x83\xb4\n");
+        let entries = Source::Zsh.parse(&contents);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].timestamp(), 1697089645);
+        assert_eq!(entries[0].timing().unwrap().wall.as_secs(), 3);
+        assert_eq!(entries[1].command(), "echo a\nb");
+        assert_eq!(entries[2].command(), "echo —");
+    }
+
+    #[test]
+    fn test_parse_fish() {
+        let contents = "- cmd: echo \"a\\nb\"\n  when: 1697089645\n  paths:\n    - src\n- cmd: ls\n  when: 1697089650\n";
+        let entries = parse_fish(contents);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].command(), "echo \"a\nb\"");
+        assert_eq!(entries[0].timestamp(), 1697089645);
+        assert_eq!(entries[1].command(), "ls");
+    }
+}
@@ -1,14 +1,145 @@
 use serde::{Deserialize, Serialize};
+use std::{path, time};
+use crate::execution::{CommandTiming, StatusCode};
+use crate::{state, utils};
 
 pub mod ux_layer;
 pub mod data_layer;
+pub mod protocol;
+pub mod import;
+pub mod export;
+pub mod privacy;
+pub mod retention;
+pub mod encryption;
+pub mod stats;
+pub mod usage;
+#[cfg(unix)]
+pub mod daemon;
 
-const BUFFER_SIZE: usize = 16384;
+/// Lines in the history file written in the current format start with this. Anything else is
+/// read as a legacy `<timestamp>[,<wall ms>,<cpu ms>] <command>` line.
+const ENTRY_V2_PREFIX: &str = "v2 ";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
     timestamp: u64,
     command: String,
+    #[serde(default)]
+    timing: Option<CommandTiming>,
+    #[serde(default)]
+    exit_status: Option<StatusCode>,
+    #[serde(default)]
+    cwd: Option<path::PathBuf>,
+    #[serde(default)]
+    hostname: Option<String>,
+    #[serde(default)]
+    session_id: Option<String>,
+    /// Secrets in `command` were replaced with `privacy::REDACTED`.
+    #[serde(default)]
+    redacted: bool,
+    /// How often the command was run, counting older duplicates folded into this entry when the
+    /// history was compacted, see `retention`.
+    #[serde(default = "default_uses")]
+    uses: u32,
+}
+
+fn default_uses() -> u32 {
+    1
+}
+
+/// Which entries history navigation and search go through, cycled with `Ctrl+G`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum ScopeKind {
+    #[default]
+    Global,
+    Session,
+    /// The git repository the shell is in, or the current directory outside of one.
+    Directory,
+}
+
+impl ScopeKind {
+    pub fn next(self) -> Self {
+        match self {
+            Self::Global => Self::Session,
+            Self::Session => Self::Directory,
+            Self::Directory => Self::Global,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Global => "global",
+            Self::Session => "session",
+            Self::Directory => "directory",
+        }
+    }
+}
+
+/// A `ScopeKind` resolved against the state of one shell, as sent to the data layer.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum HistoryScope {
+    Global,
+    Session(String),
+    /// Entries run in this directory, or one below it.
+    Directory(path::PathBuf),
+}
+
+impl HistoryScope {
+    pub fn resolve(program_state: &state::ProgramState) -> Self {
+        match program_state.history_scope {
+            ScopeKind::Global => Self::Global,
+            ScopeKind::Session => Self::Session(program_state.session_id.clone()),
+            ScopeKind::Directory => {
+                let cwd = &program_state.current_working_directory;
+                Self::Directory(utils::git_root(cwd).unwrap_or_else(|| cwd.clone()))
+            }
+        }
+    }
+}
+
+/// Which entries `HistoryRequest::Query` returns. Every condition that is set must hold.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryFilter {
+    /// Case insensitive substring of the command.
+    pub contains: Option<String>,
+    /// The entry was run in this directory, or one below it.
+    pub cwd: Option<path::PathBuf>,
+    /// Inclusive bounds, in seconds since the unix epoch.
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub exit_status: Option<StatusCode>,
+    /// Only entries that exited with a non-zero status.
+    pub failed: bool,
+    pub session_id: Option<String>,
+}
+
+impl HistoryFilter {
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        if let Some(contains) = &self.contains {
+            if !entry.command.to_lowercase().contains(&contains.to_lowercase()) {
+                return false;
+            }
+        }
+        if let Some(cwd) = &self.cwd {
+            if !entry.cwd.as_ref().is_some_and(|entry_cwd| entry_cwd.starts_with(cwd)) {
+                return false;
+            }
+        }
+        if self.since.is_some_and(|since| entry.timestamp < since)
+            || self.until.is_some_and(|until| entry.timestamp > until) {
+            return false;
+        }
+        if self.exit_status.is_some() && entry.exit_status != self.exit_status {
+            return false;
+        }
+        if self.failed && entry.exit_status.is_none_or(|status| status == 0) {
+            return false;
+        }
+        if self.session_id.is_some() && entry.session_id != self.session_id {
+            return false;
+        }
+        true
+    }
 }
 
 impl HistoryEntry {
@@ -16,6 +147,13 @@ impl HistoryEntry {
         Self {
             timestamp,
             command,
+            timing: None,
+            exit_status: None,
+            cwd: None,
+            hostname: None,
+            session_id: None,
+            redacted: false,
+            uses: default_uses(),
         }
     }
 
@@ -27,26 +165,113 @@ impl HistoryEntry {
         self.timestamp
     }
 
+    pub fn timing(&self) -> Option<CommandTiming> {
+        self.timing
+    }
+
+    pub fn exit_status(&self) -> Option<StatusCode> {
+        self.exit_status
+    }
+
+    pub fn cwd(&self) -> Option<&path::PathBuf> {
+        self.cwd.as_ref()
+    }
+
+    pub fn hostname(&self) -> Option<&str> {
+        self.hostname.as_deref()
+    }
+
+    pub fn session_id(&self) -> Option<&str> {
+        self.session_id.as_deref()
+    }
+
+    pub fn redacted(&self) -> bool {
+        self.redacted
+    }
+
+    pub fn uses(&self) -> u32 {
+        self.uses
+    }
+
     fn to_line(&self) -> String {
-        format!("{} {}\n", self.timestamp, self.command)
+        // Serializing a struct of plain fields to JSON cannot fail.
+        format!("{}{}\n", ENTRY_V2_PREFIX, serde_json::to_string(self).unwrap())
     }
 
     fn from_line(line: &str, line_number: u32) -> anyhow::Result<Self> {
-        for (i, c) in line.chars().enumerate() {
+        match line.strip_prefix(ENTRY_V2_PREFIX) {
+            Some(json) => serde_json::from_str(json).map_err(|e| {
+                anyhow::anyhow!("Failed to parse history entry on line {}: {}", line_number, e)
+            }),
+            None => Self::from_legacy_line(line, line_number),
+        }
+    }
+
+    fn from_legacy_line(line: &str, line_number: u32) -> anyhow::Result<Self> {
+        let parse_u64 = |s: &str| s.parse::<u64>().map_err(|e| {
+            anyhow::anyhow!("Failed to parse timestamp from history file: {}", e)
+        });
+
+        for (i, c) in line.char_indices() {
             if c == ' ' {
-                let timestamp = line[..i].parse::<u64>().map_err(|e| {
-                    anyhow::anyhow!("Failed to parse timestamp from history file: {}", e)
-                })?;
+                let mut fields = line[..i].split(',');
+                let timestamp = parse_u64(fields.next().unwrap_or_default())?;
+                let timing = match (fields.next(), fields.next()) {
+                    (Some(wall), Some(cpu)) => Some(CommandTiming {
+                        wall: time::Duration::from_millis(parse_u64(wall)?),
+                        cpu: time::Duration::from_millis(parse_u64(cpu)?),
+                    }),
+                    _ => None,
+                };
                 if i + 1 >= line.len() {
                     return Err(anyhow::anyhow!(
                         "Index out of bounds for parsing historical entry on line: {}",
                         line_number
                     ));
                 }
-                return Ok(Self::new(timestamp, line[i + 1..].to_string()));
+                let mut entry = Self::new(timestamp, line[i + 1..].to_string());
+                entry.timing = timing;
+                return Ok(entry);
             }
         }
 
         Err(anyhow::anyhow!("Empty line on line: {}", line_number))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::HistoryEntry;
+
+    #[test]
+    fn test_history_entry_legacy_lines() {
+        let entry = HistoryEntry::from_line("1696752681 ls -la", 0).unwrap();
+        assert_eq!(entry.timestamp(), 1696752681);
+        assert_eq!(entry.command(), "ls -la");
+        assert!(entry.timing().is_none());
+
+        let entry = HistoryEntry::from_line("1696752681,1500,20 cargo build", 1).unwrap();
+        assert_eq!(entry.command(), "cargo build");
+        assert_eq!(entry.timing().unwrap().wall.as_millis(), 1500);
+        assert_eq!(entry.timing().unwrap().cpu.as_millis(), 20);
+
+        assert!(HistoryEntry::from_line("not a timestamp", 2).is_err());
+    }
+
+    #[test]
+    fn test_history_entry_round_trip() {
+        let mut entry = HistoryEntry::new(1697089645, "echo \"a b\"".to_string());
+        entry.exit_status = Some(1);
+        entry.cwd = Some(std::path::PathBuf::from("/tmp"));
+        entry.session_id = Some("abc".to_string());
+
+        let line = entry.to_line();
+        assert!(line.starts_with("v2 "));
+        let read = HistoryEntry::from_line(line.trim_end(), 0).unwrap();
+        assert_eq!(read.command(), entry.command());
+        assert_eq!(read.exit_status(), Some(1));
+        assert_eq!(read.cwd(), entry.cwd());
+        assert_eq!(read.session_id(), Some("abc"));
+        assert_eq!(read.hostname(), None);
+    }
+}
@@ -0,0 +1,167 @@
+//! Wire format between `ux_layer::History` and the data layer.
+//! Every message is a bincode encoded `HistoryRequest` or `HistoryResponse`, prefixed with its
+//! length as a big endian `u32`. The first exchange on every connection is a `Hello` carrying
+//! `PROTOCOL_VERSION`, `Hello` is therefore always the first variant of both enums.
+
+use std::{fmt, fs, io, path};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use crate::config;
+
+/// Bump whenever `HistoryRequest`, `HistoryResponse` or `HistoryEntry` change shape.
+pub const PROTOCOL_VERSION: u32 = 8;
+
+/// Anything larger is treated as a corrupt stream rather than allocated.
+const MAX_MESSAGE_LEN: u32 = 64 * 1024 * 1024;
+
+const SOCKET_NAME: &str = "history.sock";
+
+#[derive(Debug)]
+pub enum HistoryError {
+    Io(io::Error),
+    Encoding(bincode::Error),
+    MessageTooLarge(u32),
+    VersionMismatch { client: u32, server: u32 },
+    UnexpectedResponse,
+    /// The data layer could not carry out the request.
+    Server(String),
+    /// The lock around the history was poisoned by a panicking thread.
+    Poisoned,
+    /// The indices in the request were read before entries moved, e.g. by compaction.
+    StaleIndices,
+    /// The history file could not be encrypted or decrypted, see `encryption`.
+    Encryption(String),
+    /// No history is kept by this process, see `ux_layer::History`.
+    Disabled,
+}
+
+impl fmt::Display for HistoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "history connection failed: {}", e),
+            Self::Encoding(e) => write!(f, "invalid history message: {}", e),
+            Self::MessageTooLarge(len) => write!(f, "history message of {} bytes is too large", len),
+            Self::VersionMismatch { client, server } => write!(
+                f,
+                "history protocol version {} does not match the data layer's version {}",
+                client, server
+            ),
+            Self::UnexpectedResponse => write!(f, "unexpected response from history data layer"),
+            Self::Server(e) => write!(f, "history data layer error: {}", e),
+            Self::Poisoned => write!(f, "history data layer lock was poisoned"),
+            Self::StaleIndices => write!(f, "the history changed since its indices were listed"),
+            Self::Encryption(e) => write!(f, "history encryption failed: {}", e),
+            Self::Disabled => write!(f, "history is disabled"),
+        }
+    }
+}
+
+impl std::error::Error for HistoryError {}
+
+impl From<io::Error> for HistoryError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<bincode::Error> for HistoryError {
+    fn from(e: bincode::Error) -> Self {
+        Self::Encoding(e)
+    }
+}
+
+pub fn write_message<W: io::Write, T: Serialize>(w: &mut W, message: &T) -> Result<(), HistoryError> {
+    let bytes = bincode::serialize(message)?;
+    let len = u32::try_from(bytes.len()).unwrap_or(u32::MAX);
+    if len > MAX_MESSAGE_LEN {
+        return Err(HistoryError::MessageTooLarge(len));
+    }
+    w.write_all(&len.to_be_bytes())?;
+    w.write_all(&bytes)?;
+    w.flush()?;
+    Ok(())
+}
+
+/// `None` if the other end closed the connection cleanly before a new message started.
+pub fn read_message<R: io::Read, T: DeserializeOwned>(r: &mut R) -> Result<Option<T>, HistoryError> {
+    let mut len = [0; 4];
+    match r.read_exact(&mut len) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len);
+    if len > MAX_MESSAGE_LEN {
+        return Err(HistoryError::MessageTooLarge(len));
+    }
+    let mut bytes = vec![0; len as usize];
+    r.read_exact(&mut bytes)?;
+    Ok(Some(bincode::deserialize(&bytes)?))
+}
+
+/// `$XDG_RUNTIME_DIR/whale`, or `/tmp/whale-<uid>` when that isn't set.
+#[cfg(unix)]
+pub fn runtime_dir() -> path::PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => path::PathBuf::from(dir).join("whale"),
+        _ => std::env::temp_dir().join(format!("whale-{}", unsafe { libc::getuid() })),
+    }
+}
+
+/// Creates the runtime directory readable by the current user only, refusing to use one that
+/// belongs to somebody else or that other users can access.
+#[cfg(unix)]
+pub fn ensure_runtime_dir(dir: &path::Path) -> Result<(), HistoryError> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    match fs::DirBuilder::new().recursive(true).mode(0o700).create(dir) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+        Err(e) => return Err(e.into()),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid {
+        return Err(HistoryError::Io(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a directory owned by the current user", dir.display()),
+        )));
+    }
+    if metadata.permissions().mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(())
+}
+
+/// `ConfigHistory::socket_path` if set, otherwise `history.sock` in the runtime directory.
+#[cfg(unix)]
+pub fn socket_path(config: &config::FullConfig) -> path::PathBuf {
+    match &config.history.socket_path {
+        Some(socket_path) => socket_path.clone(),
+        None => runtime_dir().join(SOCKET_NAME),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_framing_round_trip() {
+        let mut stream = vec![];
+        write_message(&mut stream, &String::from("ls -la")).unwrap();
+        write_message(&mut stream, &42u64).unwrap();
+        assert_eq!(&stream[..4], &14u32.to_be_bytes());
+
+        let mut reader = io::Cursor::new(stream);
+        assert_eq!(read_message::<_, String>(&mut reader).unwrap(), Some(String::from("ls -la")));
+        assert_eq!(read_message::<_, u64>(&mut reader).unwrap(), Some(42));
+        assert_eq!(read_message::<_, u64>(&mut reader).unwrap(), None);
+
+        let mut reader = io::Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        assert!(matches!(
+            read_message::<_, u64>(&mut reader),
+            Err(HistoryError::MessageTooLarge(_))
+        ));
+    }
+}
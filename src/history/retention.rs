@@ -0,0 +1,185 @@
+//! Which entries are kept when the history file is compacted. Everything here is linear in the
+//! number of entries, so it is cheap enough to run whenever the file grows past its limits.
+
+use std::collections::HashMap;
+use crate::config;
+use super::HistoryEntry;
+
+#[derive(Debug, Clone)]
+pub struct RetentionPolicy {
+    /// `0` for no limit.
+    pub max_entries: usize,
+    /// In seconds, `0` for no limit.
+    pub max_age: u64,
+    pub max_file_size_bytes: u64,
+    pub dedupe: bool,
+    /// Commands run at least this often are kept regardless of `max_entries` and `max_age`. `0`
+    /// turns this off.
+    pub keep_frequent_uses: u32,
+    /// Entries take more space in an encrypted file, see `line_len`.
+    pub encrypted: bool,
+}
+
+impl RetentionPolicy {
+    pub fn from_config(config: &config::history::ConfigHistory) -> Self {
+        Self {
+            max_entries: config.max_entries,
+            max_age: config.max_age_days * 24 * 60 * 60,
+            max_file_size_bytes: config.max_file_size_bytes,
+            dedupe: config.dedupe,
+            keep_frequent_uses: config.keep_frequent_uses,
+            encrypted: config.encrypt,
+        }
+    }
+
+    /// Bytes `history_entry` takes in the history file.
+    fn line_len(&self, history_entry: &HistoryEntry) -> u64 {
+        let len = history_entry.to_line().len();
+        match self.encrypted {
+            true => super::encryption::encrypted_line_len(len) as u64,
+            false => len as u64,
+        }
+    }
+
+    /// Whether the history has outgrown the policy, so that `apply` should be run. A tenth more
+    /// than `max_entries` is allowed, so that the file isn't rewritten after every command.
+    pub fn exceeded(&self, num_entries: usize, file_size_bytes: u64) -> bool {
+        (self.max_entries != 0 && num_entries > self.max_entries + self.max_entries / 10)
+            || file_size_bytes > self.max_file_size_bytes
+    }
+
+    /// Returns the entries to keep, oldest first. `history` must be sorted by timestamp.
+    ///
+    /// Duplicates are folded into their newest entry, which takes over their use count. Entries
+    /// are then dropped oldest first until at most `max_entries` are left, and if the file is over
+    /// `max_file_size_bytes`, until it would take three quarters of that. Frequently used commands
+    /// are only dropped to meet the file size.
+    pub fn apply(&self, history: Vec<HistoryEntry>, now: u64) -> Vec<HistoryEntry> {
+        let mut history = if self.dedupe { dedupe(history) } else { history };
+
+        let frequent = |history_entry: &HistoryEntry| {
+            self.keep_frequent_uses != 0 && history_entry.uses >= self.keep_frequent_uses
+        };
+        if self.max_age != 0 {
+            let oldest = now.saturating_sub(self.max_age);
+            history.retain(|history_entry| history_entry.timestamp >= oldest || frequent(history_entry));
+        }
+
+        let mut size = history.iter().map(|history_entry| self.line_len(history_entry)).sum::<u64>();
+        let target_size = match size > self.max_file_size_bytes {
+            true => self.max_file_size_bytes / 4 * 3,
+            false => size,
+        };
+        let mut excess = match self.max_entries {
+            0 => 0,
+            max_entries => history.len().saturating_sub(max_entries),
+        };
+
+        // Infrequent entries go first, then frequent ones if the file is still too big
+        let mut keep = vec![true; history.len()];
+        for drop_frequent in [false, true] {
+            for (i, history_entry) in history.iter().enumerate() {
+                if excess == 0 && size <= target_size {
+                    break;
+                }
+                if !keep[i] || (frequent(history_entry) && !drop_frequent) {
+                    continue;
+                }
+                if drop_frequent && size <= target_size {
+                    break;
+                }
+                keep[i] = false;
+                excess = excess.saturating_sub(1);
+                size -= self.line_len(history_entry);
+            }
+        }
+
+        let mut i = 0;
+        history.retain(|_| {
+            i += 1;
+            keep[i - 1]
+        });
+        history
+    }
+}
+
+/// Keeps the newest entry of every command, adding the uses of the older ones to it.
+fn dedupe(history: Vec<HistoryEntry>) -> Vec<HistoryEntry> {
+    let mut newest = HashMap::with_capacity(history.len());
+    let mut deduped: Vec<HistoryEntry> = Vec::with_capacity(history.len());
+    for history_entry in history.into_iter().rev() {
+        match newest.get(&history_entry.command) {
+            Some(&i) => {
+                let kept: &mut HistoryEntry = &mut deduped[i];
+                kept.uses = kept.uses.saturating_add(history_entry.uses);
+            }
+            None => {
+                newest.insert(history_entry.command.clone(), deduped.len());
+                deduped.push(history_entry);
+            }
+        }
+    }
+    deduped.reverse();
+    deduped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetentionPolicy {
+        RetentionPolicy {
+            max_entries: 0,
+            max_age: 0,
+            max_file_size_bytes: u64::MAX,
+            dedupe: true,
+            keep_frequent_uses: 3,
+            encrypted: false,
+        }
+    }
+
+    fn entries(commands: &[&str]) -> Vec<HistoryEntry> {
+        commands
+            .iter()
+            .enumerate()
+            .map(|(i, cmd)| HistoryEntry::new(i as u64 + 1, cmd.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_retention_dedupe() {
+        let kept = policy().apply(entries(&["ls", "cd", "ls", "ls", "pwd"]), 10);
+        let commands = kept.iter().map(|e| e.command()).collect::<Vec<_>>();
+        assert_eq!(commands, ["cd", "ls", "pwd"]);
+        assert_eq!(kept[1].timestamp(), 4);
+        assert_eq!(kept[1].uses(), 3);
+
+        // No duplicates at all
+        assert_eq!(policy().apply(entries(&["a", "b"]), 10).len(), 2);
+        assert!(policy().apply(vec![], 10).is_empty());
+    }
+
+    #[test]
+    fn test_retention_limits() {
+        let mut policy = policy();
+        policy.max_entries = 2;
+        let kept = policy.apply(entries(&["ls", "a", "ls", "b", "ls", "c"]), 10);
+        // `ls` is frequent, so the older `b` goes instead
+        let commands = kept.iter().map(|e| e.command()).collect::<Vec<_>>();
+        assert_eq!(commands, ["ls", "c"]);
+
+        policy.max_entries = 0;
+        policy.max_age = 3;
+        let kept = policy.apply(entries(&["ls", "a", "ls", "b", "ls", "c"]), 8);
+        let commands = kept.iter().map(|e| e.command()).collect::<Vec<_>>();
+        assert_eq!(commands, ["ls", "c"]);
+
+        // Unique commands still shrink to fit the file size
+        policy.max_age = 0;
+        policy.max_file_size_bytes = 400;
+        let kept = policy.apply(entries(&["a", "b", "c", "d", "e", "f", "g", "h", "i", "j"]), 10);
+        let size = kept.iter().map(|e| e.to_line().len()).sum::<usize>();
+        assert!(!kept.is_empty() && size <= 300);
+        assert_eq!(kept.last().unwrap().command(), "j");
+    }
+}
@@ -0,0 +1,146 @@
+//! Usage statistics over the history, computed by the data layer for `history stats`.
+
+use std::collections::HashMap;
+use std::path;
+use serde::{Deserialize, Serialize};
+use super::HistoryEntry;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutableStats {
+    pub name: String,
+    pub uses: u32,
+    /// Runs whose exit status is known, which the failure rate is out of.
+    pub with_status: u32,
+    pub failures: u32,
+}
+
+impl ExecutableStats {
+    /// `None` if no run of the executable has a recorded exit status.
+    pub fn failure_rate(&self) -> Option<f64> {
+        (self.with_status > 0).then(|| self.failures as f64 / self.with_status as f64)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HistoryStats {
+    /// Commands run, counting duplicates folded together by compaction.
+    pub total: u32,
+    /// Most used first.
+    pub commands: Vec<(String, u32)>,
+    /// Most used first. Every executable is included, so callers can look for unconfigured ones.
+    pub executables: Vec<ExecutableStats>,
+    /// Most used first.
+    pub directories: Vec<(path::PathBuf, u32)>,
+    /// Commands started in each hour of the day, in UTC like `utils::format_timestamp`.
+    pub hours: [u32; 24],
+    /// In characters.
+    pub average_length: f64,
+}
+
+/// The words of a command from the program it runs on, skipping leading `NAME=value` assignments.
+pub fn words_after_assignments(command: &str) -> impl Iterator<Item=&str> {
+    command
+        .split_whitespace()
+        .skip_while(|word| word.split_once('=').is_some_and(|(name, _)| !name.is_empty() && !name.contains('/')))
+}
+
+/// `word` without any directories, e.g. `cargo` for `/usr/bin/cargo`.
+pub fn executable_name(word: &str) -> Option<&str> {
+    word.rsplit('/').next().filter(|name| !name.is_empty())
+}
+
+/// The program a command runs, skipping leading `NAME=value` assignments and any directories.
+pub fn executable(command: &str) -> Option<&str> {
+    words_after_assignments(command).next().and_then(executable_name)
+}
+
+fn most_used_first<K: Ord>(counts: HashMap<K, u32>) -> Vec<(K, u32)> {
+    let mut counts = counts.into_iter().collect::<Vec<(K, u32)>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Only the `top` most used commands and directories are kept.
+pub fn compute<'a>(entries: impl Iterator<Item=&'a HistoryEntry>, top: usize) -> HistoryStats {
+    let mut stats = HistoryStats::default();
+    let mut commands = HashMap::new();
+    let mut directories = HashMap::new();
+    let mut executables: HashMap<&str, ExecutableStats> = HashMap::new();
+    let mut total_length = 0u64;
+
+    for history_entry in entries {
+        let uses = history_entry.uses;
+        stats.total += uses;
+        total_length += history_entry.command.chars().count() as u64 * uses as u64;
+        *commands.entry(history_entry.command.as_str()).or_insert(0) += uses;
+        if let Some(cwd) = &history_entry.cwd {
+            *directories.entry(cwd.as_path()).or_insert(0) += uses;
+        }
+        if history_entry.timestamp != 0 {
+            stats.hours[(history_entry.timestamp / 3600 % 24) as usize] += uses;
+        }
+        if let Some(name) = executable(&history_entry.command) {
+            let executable = executables.entry(name).or_insert_with(|| ExecutableStats {
+                name: name.to_string(),
+                uses: 0,
+                with_status: 0,
+                failures: 0,
+            });
+            executable.uses += uses;
+            // Folded duplicates only keep the exit status of their last run
+            if let Some(status) = history_entry.exit_status {
+                executable.with_status += 1;
+                if status != 0 {
+                    executable.failures += 1;
+                }
+            }
+        }
+    }
+
+    if stats.total > 0 {
+        stats.average_length = total_length as f64 / stats.total as f64;
+    }
+    stats.commands = most_used_first(commands)
+        .into_iter()
+        .take(top)
+        .map(|(command, uses)| (command.to_string(), uses))
+        .collect();
+    stats.directories = most_used_first(directories)
+        .into_iter()
+        .take(top)
+        .map(|(dir, uses)| (dir.to_path_buf(), uses))
+        .collect();
+    let mut executables = executables.into_values().collect::<Vec<ExecutableStats>>();
+    executables.sort_by(|a, b| b.uses.cmp(&a.uses).then_with(|| a.name.cmp(&b.name)));
+    stats.executables = executables;
+    stats
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_stats() {
+        assert_eq!(executable("RUST_LOG=debug /usr/bin/cargo run"), Some("cargo"));
+        assert_eq!(executable("  "), None);
+
+        let mut entries = vec![
+            HistoryEntry::new(3600, "cargo build".to_string()),
+            HistoryEntry::new(3601, "cargo test".to_string()),
+            HistoryEntry::new(7200, "ls".to_string()),
+        ];
+        entries[0].exit_status = Some(0);
+        entries[1].exit_status = Some(101);
+        entries[2].uses = 3;
+
+        let stats = compute(entries.iter(), 10);
+        assert_eq!(stats.total, 5);
+        assert_eq!(stats.commands[0], ("ls".to_string(), 3));
+        assert_eq!(stats.hours[1], 2);
+        assert_eq!(stats.hours[2], 3);
+        assert_eq!(stats.executables[0].name, "ls");
+        assert_eq!(stats.executables[1].failure_rate(), Some(0.5));
+        assert_eq!(stats.executables[0].failure_rate(), None);
+    }
+}
@@ -0,0 +1,78 @@
+//! How often, and how recently, words were used in the history, which hints are ranked by.
+
+use std::collections::HashMap;
+use std::path;
+use super::{stats, HistoryEntry};
+
+/// A use this many seconds ago counts half as much as one made now.
+const HALF_LIFE: f64 = 14.0 * 24.0 * 60.0 * 60.0;
+
+fn weight(history_entry: &HistoryEntry, now: u64) -> f64 {
+    // Imported entries may not have a timestamp, they count as if made one half life ago
+    let age = match history_entry.timestamp {
+        0 => HALF_LIFE,
+        timestamp => now.saturating_sub(timestamp) as f64,
+    };
+    history_entry.uses as f64 * 0.5f64.powf(age / HALF_LIFE)
+}
+
+/// Scores of the executables that were run, or with `Some(executable)`, of the words passed to it.
+/// Path arguments also score for their file name, as that is what path hints list.
+pub fn compute<'a>(
+    entries: impl Iterator<Item=&'a HistoryEntry>,
+    executable: Option<&str>,
+    now: u64,
+) -> HashMap<String, f64> {
+    let mut scores: HashMap<String, f64> = HashMap::new();
+    for history_entry in entries {
+        let mut words = stats::words_after_assignments(&history_entry.command);
+        let name = match words.next().and_then(stats::executable_name) {
+            Some(name) => name,
+            None => continue,
+        };
+        let weight = weight(history_entry, now);
+        match executable {
+            None => *scores.entry(name.to_string()).or_insert(0.0) += weight,
+            Some(executable) if executable == name => {
+                for word in words {
+                    *scores.entry(word.to_string()).or_insert(0.0) += weight;
+                    if word.contains('/') {
+                        if let Some(file_name) = path::Path::new(word).file_name() {
+                            *scores.entry(file_name.to_string_lossy().into_owned()).or_insert(0.0) += weight;
+                        }
+                    }
+                }
+            }
+            Some(_) => (),
+        }
+    }
+    scores
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usage_scores() {
+        let day = 24 * 60 * 60;
+        let entries = vec![
+            HistoryEntry::new(100 * day, "git checkout main".to_string()),
+            HistoryEntry::new(100 * day, "git checkout dev".to_string()),
+            HistoryEntry::new(99 * day, "git cherry-pick abc".to_string()),
+            HistoryEntry::new(40 * day, "git cherry-pick def".to_string()),
+            HistoryEntry::new(100 * day, "vim src/main.rs".to_string()),
+        ];
+        let now = 100 * day;
+
+        let executables = compute(entries.iter(), None, now);
+        assert!(executables["git"] > executables["vim"]);
+
+        let git = compute(entries.iter(), Some("git"), now);
+        assert!(git["checkout"] > git["cherry-pick"]);
+        assert!(!git.contains_key("src/main.rs"));
+
+        let vim = compute(entries.iter(), Some("vim"), now);
+        assert_eq!(vim["main.rs"], vim["src/main.rs"]);
+    }
+}
@@ -1,137 +1,378 @@
 use std::cell::RefCell;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::collections::HashMap;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 use std::rc::Rc;
-use crate::history::{BUFFER_SIZE, HistoryEntry};
-use crate::history::data_layer::{HistoryRequest, HistoryResponse};
-use crate::state;
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time;
+use crate::history::{HistoryEntry, HistoryFilter, HistoryScope};
+use crate::history::data_layer::{self, HistoryRequest, HistoryResponse, SearchMatch};
+use crate::history::privacy::HistoryPrivacy;
+use crate::history::stats::HistoryStats;
+use crate::history::protocol::{self, HistoryError};
+use crate::{config, execution, fuzzy, state};
+
+/// Shared by every `History` in this process that could not reach a data layer over the socket.
+/// `None` if the history file could not be read.
+static LOCAL_HISTORY: OnceLock<Option<data_layer::SharedHistory>> = OnceLock::new();
+
+/// Every `History` in this process would fail the same way, so it is only told once.
+#[cfg(unix)]
+static REPORT_VERSION_MISMATCH: std::sync::Once = std::sync::Once::new();
+
+#[derive(Debug)]
+enum Connection {
+    #[cfg(unix)]
+    Socket(UnixStream),
+    Local(data_layer::SharedHistory),
+    /// Every request fails with `HistoryError::Disabled`.
+    Disabled,
+}
 
 #[derive(Debug)]
 pub struct History {
     program_state: Rc<RefCell<state::ProgramState>>,
-    data_conn: TcpStream,
-    buffer: [u8; BUFFER_SIZE],
+    data_conn: Connection,
+    privacy: HistoryPrivacy,
     history_iter: usize,
+    /// Generation of the data layer's indices that `history_iter` refers to.
+    history_generation: u64,
     history_uncommitted: Option<HistoryEntry>,
+    /// Command of the entry last returned while stepping through the history.
+    history_shown: Option<String>,
+    /// The scope `history_iter` steps through, fixed when navigation starts.
+    navigation_scope: HistoryScope,
 }
 
 impl History {
-    pub fn fix_connection(&mut self) {
-        super::data_layer::start_history_data_layer(self.program_state.clone())
-            .unwrap();
-        let port = self.program_state.borrow().config.history.tcp_port;
-        self.data_conn = TcpStream::connect(format!("127.0.0.1:{}", port)).unwrap();
+    /// Connects to the data layer, starting one in this process if there isn't one running. Falls
+    /// back to keeping the history in process only if no data layer could be reached at all. One
+    /// that speaks a different protocol version still writes to the history file, so rather than
+    /// write to it as well, the shell goes without a history, as it also does if the file can't be
+    /// read.
+    fn connect(config: &config::FullConfig) -> Connection {
+        #[cfg(unix)]
+        match Self::connect_socket(config) {
+            Ok(stream) => return Connection::Socket(stream),
+            Err(e @ HistoryError::VersionMismatch { .. }) => {
+                REPORT_VERSION_MISMATCH.call_once(|| eprintln!("History is disabled: {}", e));
+                return Connection::Disabled;
+            }
+            Err(_) => (),
+        }
+        let history = LOCAL_HISTORY.get_or_init(|| {
+            match data_layer::DataLayerHistory::init(config) {
+                Ok(history) => Some(Arc::new(RwLock::new(history))),
+                Err(e) => {
+                    eprintln!("Failed to read the history file, history is disabled: {}", e);
+                    None
+                }
+            }
+        });
+        match history {
+            Some(history) => Connection::Local(history.clone()),
+            None => Connection::Disabled,
+        }
     }
 
-    pub fn init(program_state: Rc<RefCell<state::ProgramState>>) -> Self {
-        let data_conn = {
-            let p_state = program_state.borrow();
-            let port = p_state.config.history.tcp_port;
-            let addr = format!("127.0.0.1:{}", port);
-            match TcpStream::connect(&addr) {
-                Ok(stream) => stream,
-                Err(_no_server) => {
-                    super::data_layer::start_history_data_layer(program_state.clone())
-                        .unwrap();
-                    TcpStream::connect(addr).unwrap()
-                }
+    /// Prefers a `whale-historyd` daemon, as it outlives this process, over serving the history
+    /// from a thread in this process. Gives up on a data layer of another version straight away.
+    #[cfg(unix)]
+    fn connect_socket(config: &config::FullConfig) -> Result<UnixStream, HistoryError> {
+        match data_layer::connect(config) {
+            Ok(stream) => return Ok(stream),
+            Err(e @ HistoryError::VersionMismatch { .. }) => return Err(e),
+            Err(_) => (),
+        }
+        if config.history.spawn_daemon && super::daemon::spawn(config).is_ok() {
+            match data_layer::connect(config) {
+                Ok(stream) => return Ok(stream),
+                Err(e @ HistoryError::VersionMismatch { .. }) => return Err(e),
+                Err(_) => (),
             }
-        };
+        }
+        // Another process may have won the race to start one, in which case we use theirs
+        let _ = data_layer::start_history_data_layer(config);
+        data_layer::connect(config)
+    }
+
+    pub fn init(program_state: Rc<RefCell<state::ProgramState>>) -> Self {
+        let data_conn = Self::connect(&program_state.borrow().config);
+        let privacy = HistoryPrivacy::init(&program_state.borrow().config.history);
         let mut s = Self {
             program_state: program_state.clone(),
             data_conn,
-            buffer: [0; BUFFER_SIZE],
+            privacy,
             history_uncommitted: None,
+            history_shown: None,
+            navigation_scope: HistoryScope::Global,
             history_iter: 0,
+            history_generation: 0,
         };
         s.update_oldest_ind();
         s
     }
 
-    fn write_buf(&mut self, data: HistoryRequest) -> Result<usize, std::io::Error> {
-        let x = self.data_conn.write(&bincode::serialize(&data).unwrap());
-        if x.is_err() {
-            self.fix_connection();
-            self.write_buf(data)
-        } else {
-            x
+    fn send(&mut self, request: &HistoryRequest) -> Result<HistoryResponse, HistoryError> {
+        match &mut self.data_conn {
+            #[cfg(unix)]
+            Connection::Socket(stream) => {
+                protocol::write_message(stream, request)?;
+                protocol::read_message(stream)?.ok_or_else(|| {
+                    HistoryError::Io(std::io::ErrorKind::UnexpectedEof.into())
+                })
+            }
+            Connection::Local(history) => Ok(data_layer::respond(history, request.clone())),
+            Connection::Disabled => Err(HistoryError::Disabled),
         }
     }
 
-    fn read_buf(&mut self) -> anyhow::Result<HistoryResponse> {
-        let bytes = self.data_conn.read(&mut self.buffer)?;
-        bincode::deserialize(&self.buffer[..bytes]).map_err(|e| {
-            anyhow::anyhow!("Failed to deserialize history response: {}", e)
-        })
+    /// If the connection broke, e.g. because the process serving the history exited, it is made
+    /// again and the request retried once.
+    fn request(&mut self, request: HistoryRequest) -> Result<HistoryResponse, HistoryError> {
+        let resp = match self.send(&request) {
+            Err(HistoryError::Io(_)) => {
+                self.data_conn = Self::connect(&self.program_state.borrow().config);
+                self.send(&request)?
+            }
+            resp => resp?,
+        };
+        match resp {
+            HistoryResponse::Error(e) => Err(HistoryError::Server(e)),
+            resp => Ok(resp),
+        }
     }
 
     fn update_oldest_ind(&mut self) {
-        self.write_buf(HistoryRequest::GetNumHistoryEntries).unwrap();
-        match self.read_buf() {
-            Ok(HistoryResponse::HistoryInd(ind)) => {
-                self.history_iter = ind;
-            }
-            Err(_) => self.update_oldest_ind(),
-            _ => unreachable!("Invalid response from history data layer"),
+        if let Ok(HistoryResponse::HistoryLen { len, generation }) = self.request(HistoryRequest::GetNumHistoryEntries) {
+            self.history_iter = len;
+            self.history_generation = generation;
         }
     }
 
-    pub fn add_to_history(&mut self, cmd: String) -> anyhow::Result<()> {
-        if cmd.len() == 0 {
-            return Ok(());
-        }
+    pub fn add_to_history(
+        &mut self,
+        cmd: String,
+        exit_status: Option<execution::StatusCode>,
+        timing: Option<execution::CommandTiming>,
+    ) -> anyhow::Result<()> {
+        let history_entry = {
+            let p_state = self.program_state.borrow();
+            let mut history_entry = HistoryEntry::new(
+                time::SystemTime::now().duration_since(time::UNIX_EPOCH)?.as_secs(),
+                cmd,
+            );
+            history_entry.timing = timing;
+            history_entry.exit_status = exit_status;
+            history_entry.cwd = Some(p_state.current_working_directory.clone());
+            history_entry.hostname = Some(p_state.hostname.clone());
+            history_entry.session_id = Some(p_state.session_id.clone());
+            match history_entry.command.is_empty() {
+                true => None,
+                false => self.privacy.apply(history_entry, &p_state.config.commands),
+            }
+        };
+        let history_entry = match history_entry {
+            Some(history_entry) => history_entry,
+            None => {
+                self.history_uncommitted = None;
+                self.history_shown = None;
+                return Ok(());
+            }
+        };
 
-        self.write_buf(HistoryRequest::AddToHistory(cmd.clone())).unwrap();
-        match self.read_buf() {
-            Ok(HistoryResponse::Ok) => (),
-            Err(_) => return self.add_to_history(cmd),
-            _ => unreachable!("Invalid response from history data layer"),
+        match self.request(HistoryRequest::AddToHistory(history_entry)) {
+            // Already reported when connecting
+            Ok(HistoryResponse::Ok) | Err(HistoryError::Disabled) => (),
+            Ok(_) => return Err(HistoryError::UnexpectedResponse.into()),
+            Err(e) => return Err(e.into()),
         }
 
         self.history_uncommitted = None;
+        self.history_shown = None;
         self.update_oldest_ind();
 
         Ok(())
     }
 
+    fn navigation_mode(&self) -> fuzzy::MatchMode {
+        self.program_state.borrow().config.history.navigation_match
+    }
+
+    pub fn scope(&self) -> HistoryScope {
+        HistoryScope::resolve(&self.program_state.borrow())
+    }
+
+    /// Steps to the previous entry matching whatever was typed before navigation started.
     pub fn get_older_history(&mut self, cmd: &[char]) -> Option<HistoryEntry> {
-        if self.history_iter > 0 {
-            if self.history_uncommitted.is_none() {
-                self.history_uncommitted = Some(HistoryEntry::new(0, cmd.iter().collect()));
-            }
+        let current = cmd.iter().collect::<String>();
+        let scope = self.scope();
+        if self.history_uncommitted.is_some()
+            && (self.history_shown.as_ref() != Some(&current) || self.navigation_scope != scope) {
+            // The buffer was edited or the scope changed since the last step, so start over
+            self.history_uncommitted = None;
+            self.update_oldest_ind();
+        }
+        let started = self.history_uncommitted.is_none();
+        if started {
+            self.history_uncommitted = Some(HistoryEntry::new(0, current.clone()));
+            self.navigation_scope = scope;
+        }
 
-            self.write_buf(HistoryRequest::GetHistoryInd(self.history_iter - 1)).unwrap();
-            match self.read_buf() {
-                Ok(HistoryResponse::HistoryVal(history_entry)) => {
-                    self.history_iter -= 1;
-                    return history_entry;
+        let request = HistoryRequest::FindOlder {
+            query: self.history_uncommitted.as_ref().unwrap().command.clone(),
+            mode: self.navigation_mode(),
+            scope: self.navigation_scope.clone(),
+            generation: self.history_generation,
+            from: self.history_iter,
+            skip: Some(current),
+        };
+        match self.request(request) {
+            Ok(HistoryResponse::IndexedHistoryVal { generation, found: Some((ind, history_entry)) }) => {
+                self.history_iter = ind;
+                self.history_generation = generation;
+                self.history_shown = Some(history_entry.command.clone());
+                Some(history_entry)
+            }
+            _ => {
+                if started {
+                    self.history_uncommitted = None;
                 }
-                Err(_) => return self.get_older_history(cmd),
-                _ => unreachable!("Invalid response from history data layer"),
+                None
             }
         }
-        None
     }
 
     pub fn get_newer_history(&mut self) -> Option<HistoryEntry> {
-        self.write_buf(HistoryRequest::GetHistoryInd(self.history_iter + 1)).unwrap();
-        match self.read_buf() {
-            Ok(HistoryResponse::HistoryVal(history_entry)) => {
-                self.history_iter += 1;
-                if history_entry.is_some() {
-                    return history_entry;
-                }
-            }
-            Err(_) => return self.get_newer_history(),
-            _ => unreachable!("Invalid response from history data layer"),
+        let query = match &self.history_uncommitted {
+            Some(history_entry) => history_entry.command.clone(),
+            None => return None,
+        };
+
+        let request = HistoryRequest::FindNewer {
+            query,
+            mode: self.navigation_mode(),
+            scope: self.navigation_scope.clone(),
+            generation: self.history_generation,
+            from: self.history_iter,
+            skip: self.history_shown.clone(),
+        };
+        if let Ok(HistoryResponse::IndexedHistoryVal { found: Some((ind, history_entry)), .. }) = self.request(request) {
+            self.history_iter = ind;
+            self.history_shown = Some(history_entry.command.clone());
+            return Some(history_entry);
         }
         {
-            // we have probably gone out of range. But this could be because the file has been
-            // concatenated by a new instance of the server. So instead we query for the highest index.
+            // We have gone past the newest match, so give back what was typed. The index is queried
+            // again since the file could have been concatenated by a new instance of the server.
             self.update_oldest_ind();
-            let r = self.history_uncommitted.clone();
-            self.history_uncommitted = None;
-            return r; // purposeful unwrap
+            self.history_shown = None;
+            self.history_uncommitted.take()
+        }
+    }
+
+    /// The most recent entry starting with `prefix`, used for inline suggestions.
+    pub fn suggest(&mut self, prefix: &str) -> Option<HistoryEntry> {
+        match self.request(HistoryRequest::FindFirstOccurrence(prefix.to_string())) {
+            Ok(HistoryResponse::HistoryVal(history_entry)) => history_entry,
+            _ => None,
+        }
+    }
+
+    /// Returns the total number of matches, and the matches from `offset` to `offset + limit`.
+    pub fn search(
+        &mut self,
+        query: &str,
+        mode: fuzzy::MatchMode,
+        scope: HistoryScope,
+        offset: usize,
+        limit: usize,
+    ) -> (usize, Vec<SearchMatch>) {
+        let request = HistoryRequest::Search { query: query.to_string(), mode, scope, offset, limit };
+        match self.request(request) {
+            Ok(HistoryResponse::SearchResults { total, matches }) => (total, matches),
+            _ => (0, vec![]),
+        }
+    }
+
+    /// Returns how many of the entries were added, and how many were already in the history. The
+    /// privacy settings apply as if the entries were typed, so those they leave out are neither.
+    pub fn import(&mut self, history_entries: Vec<HistoryEntry>) -> Result<(usize, usize), HistoryError> {
+        let history_entries = history_entries
+            .into_iter()
+            .filter_map(|history_entry| {
+                self.privacy.apply(history_entry, &self.program_state.borrow().config.commands)
+            })
+            .collect();
+        let resp = self.request(HistoryRequest::Import(history_entries))?;
+        self.update_oldest_ind();
+        match resp {
+            HistoryResponse::Imported { added, skipped } => Ok((added, skipped)),
+            _ => Err(HistoryError::UnexpectedResponse),
+        }
+    }
+
+    /// Also returns the generation of the indices, for `delete`.
+    pub fn query(
+        &mut self,
+        filter: HistoryFilter,
+        limit: Option<usize>,
+    ) -> Result<(u64, Vec<(usize, HistoryEntry)>), HistoryError> {
+        match self.request(HistoryRequest::Query { filter, limit })? {
+            HistoryResponse::Entries { generation, entries } => Ok((generation, entries)),
+            _ => Err(HistoryError::UnexpectedResponse),
+        }
+    }
+
+    /// Removes the entries in each inclusive range of indices, as returned by `query`. Fails with
+    /// `HistoryError::Server` if `generation` is given and the indices have changed since.
+    pub fn delete(
+        &mut self,
+        generation: Option<u64>,
+        ranges: Vec<(usize, usize)>,
+    ) -> Result<usize, HistoryError> {
+        let resp = self.request(HistoryRequest::Delete { generation, ranges })?;
+        self.update_oldest_ind();
+        match resp {
+            HistoryResponse::Deleted(removed) => Ok(removed),
+            _ => Err(HistoryError::UnexpectedResponse),
+        }
+    }
+
+    /// Returns how many entries were removed.
+    pub fn compact(&mut self) -> Result<usize, HistoryError> {
+        let resp = self.request(HistoryRequest::Compact)?;
+        self.update_oldest_ind();
+        match resp {
+            HistoryResponse::Compacted(removed) => Ok(removed),
+            _ => Err(HistoryError::UnexpectedResponse),
+        }
+    }
+
+    /// Empty if the history can't be reached, so that hints are still shown.
+    pub fn usage(&mut self, executable: Option<&str>) -> HashMap<String, f64> {
+        match self.request(HistoryRequest::Usage { executable: executable.map(str::to_string) }) {
+            Ok(HistoryResponse::Usage(usage)) => usage,
+            _ => HashMap::new(),
+        }
+    }
+
+    /// How many times each executable was run in the current scope, counted as by `stats`. Empty if
+    /// the history can't be reached.
+    pub fn executable_uses(&mut self) -> HashMap<String, u32> {
+        match self.stats(self.scope(), 0) {
+            Ok(stats) => stats.executables
+                .into_iter()
+                .map(|executable| (executable.name, executable.uses))
+                .collect(),
+            Err(_) => HashMap::new(),
+        }
+    }
+
+    pub fn stats(&mut self, scope: HistoryScope, top: usize) -> Result<HistoryStats, HistoryError> {
+        match self.request(HistoryRequest::Stats { scope, top })? {
+            HistoryResponse::Stats(stats) => Ok(stats),
+            _ => Err(HistoryError::UnexpectedResponse),
         }
     }
 }
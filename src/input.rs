@@ -15,6 +15,8 @@ pub enum InputEvent {
     CtrlT,
     CtrlS,
     CtrlD,
+    CtrlR,
+    CtrlG,
 
     ArrowUp,
     ArrowRight,
@@ -42,6 +44,15 @@ pub enum InputEvent {
     Other(Event),
 }
 
+/// Waits at most `timeout` for input, returning `None` if there was none.
+pub fn poll_input(timeout: std::time::Duration) -> Result<Option<InputEvent>, std::io::Error> {
+    if crossterm::event::poll(timeout)? {
+        get_input().map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
 pub fn get_input() -> Result<InputEvent, std::io::Error> {
     let key = crossterm::event::read()?;
 
@@ -77,6 +88,18 @@ pub fn get_input() -> Result<InputEvent, std::io::Error> {
             kind: KeyEventKind::Press,
             state: KeyEventState::NONE,
         }) => InputEvent::CtrlD,
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('r'),
+            modifiers: KeyModifiers::CONTROL,
+            kind: KeyEventKind::Press,
+            state: KeyEventState::NONE,
+        }) => InputEvent::CtrlR,
+        Event::Key(KeyEvent {
+            code: KeyCode::Char('g'),
+            modifiers: KeyModifiers::CONTROL,
+            kind: KeyEventKind::Press,
+            state: KeyEventState::NONE,
+        }) => InputEvent::CtrlG,
 
         Event::Key(KeyEvent {
             code: KeyCode::Char(c),
@@ -9,4 +9,6 @@ pub mod state;
 pub mod utils;
 pub mod parser;
 pub mod execution;
-pub mod history;
\ No newline at end of file
+pub mod history;
+pub mod tabular;
+pub mod fuzzy;
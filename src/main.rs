@@ -5,6 +5,9 @@ use whale_rs::gui::{explorer, GUITrait, ViewType};
 use whale_rs::input::InputEvent;
 use whale_rs::{ansi, buffer, config, execution, gui, input, parser, state};
 
+/// How often hints are checked for while the worker is generating them.
+const HINT_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_millis(20);
+
 fn toggle_view_action(
     view_action: &mut AdditionalViewAction,
     active_view: Option<ViewType>,
@@ -41,22 +44,32 @@ fn update_buffer(
         InputEvent::Backspace => buffer.del_n(Side::Left, 1),
         InputEvent::Delete => buffer.del_n(Side::Right, 1),
         InputEvent::Enter => {
-            let (new_line, _status) =
+            let (new_line, status, timing) =
                 execution::running::run_command(program_state.clone(), buffer, arg_parser);
             if let Some(line) = new_line {
                 terminal_gui.set_current_line(line);
             }
-            buffer.history_push_current();
+            terminal_gui.set_last_timing(timing);
+            if program_state.borrow().pending_table.is_some() {
+                rtn = AdditionalViewAction::SetTo(ViewType::Records);
+            }
+            buffer.history_push_current(status, timing);
             buffer.clear_all();
         }
         InputEvent::Tab => {
             let hints = buffer.get_argument_hints();
             let curr = buffer.arg_locs(buffer.get_curr_arg());
             let hint = &hints[buffer.get_curr_arg()].1;
-            let hint_arg = hint.last_closest_match().unwrap()
-                [(curr.1 - curr.0 - hint.disregard())..]
-                .to_string();
-            buffer.insert_str_main_cursor(&hint_arg);
+            let closest = hint.last_closest_match().unwrap().clone();
+            let typed = buffer.get_buffer_str(curr)[hint.disregard()..].to_string();
+            match closest.strip_prefix(&typed) {
+                Some(rest) => buffer.insert_str_main_cursor(rest),
+                None => {
+                    // Fuzzy and substring matches replace what was typed
+                    buffer.del_n(Side::Left, typed.chars().count());
+                    buffer.insert_str_main_cursor(&closest);
+                }
+            }
         }
         InputEvent::Character(c) => {
             buffer.del_betw_curs();
@@ -64,7 +77,7 @@ fn update_buffer(
         }
         InputEvent::CtrlBackspace => buffer.del_jump(Side::Left),
         InputEvent::CtrlDelete => buffer.del_jump(Side::Left),
-        InputEvent::CtrlC => unreachable!("This should be handled outside of the match statement!"),
+        InputEvent::CtrlC => buffer.clear_all(),
 
         InputEvent::ArrowUp => {
             buffer.history_older();
@@ -78,8 +91,10 @@ fn update_buffer(
             buffer.unset_secondary_cursor();
         }
         InputEvent::ArrowRight => {
-            buffer.main_cur_set(buffer.move_n(Side::Right, 1, buffer.main_cur()));
-            buffer.unset_secondary_cursor();
+            if !buffer.accept_suggestion() {
+                buffer.main_cur_set(buffer.move_n(Side::Right, 1, buffer.main_cur()));
+                buffer.unset_secondary_cursor();
+            }
         }
 
         InputEvent::CtrlArrowLeft => {
@@ -129,10 +144,21 @@ fn update_buffer(
             toggle_view_action(&mut rtn, view, ViewType::Table)
         }
 
+        InputEvent::CtrlR => {
+            let view = terminal_gui.view_type();
+            toggle_view_action(&mut rtn, view, ViewType::HistorySearch)
+        }
+
+        InputEvent::CtrlG => {
+            let mut p_state = program_state.borrow_mut();
+            p_state.history_scope = p_state.history_scope.next();
+        }
+
         _ => (), // TODO: History
     }
 
     buffer.update();
+    buffer.update_suggestion();
     arg_parser.reinit(buffer.first_arg());
     buffer.update_arguments(arg_parser);
     rtn
@@ -140,9 +166,10 @@ fn update_buffer(
 
 fn execute_action(
     action: gui::ActionToExecute,
+    active_view: Option<ViewType>,
     buffer: &mut buffer::InputBuffer,
     arg_parser: &mut parser::ArgumentParser,
-) {
+) -> AdditionalViewAction {
     match action {
         gui::ActionToExecute::SetClosestMatch(s) => {
             let curr_arg = buffer.get_curr_arg();
@@ -153,8 +180,15 @@ fn execute_action(
             buffer.insert_str_main_cursor(&s);
             buffer.update();
             buffer.update_arguments(arg_parser);
+            // Choosing an entry from the history search also closes it, as does clearing the filter
+            // of the records view
+            if matches!(active_view, Some(ViewType::HistorySearch | ViewType::Records)) {
+                return AdditionalViewAction::Unset;
+            }
         }
+        gui::ActionToExecute::CloseView => return AdditionalViewAction::Unset,
     }
+    AdditionalViewAction::None
 }
 
 fn update_view(
@@ -184,6 +218,14 @@ fn update_view(
                     let explorer = explorer::FileExplorerGUI::init(program_state.clone());
                     Box::new(explorer) as Box<dyn GUITrait>
                 }
+                ViewType::Records => {
+                    let records = gui::records::RecordsGUI::init(program_state.clone());
+                    Box::new(records) as Box<dyn GUITrait>
+                }
+                ViewType::HistorySearch => {
+                    let search = gui::history_search::HistorySearchGUI::init(program_state.clone());
+                    Box::new(search) as Box<dyn GUITrait>
+                }
             };
             terminal_gui.set_using(Some(trait_obj));
         }
@@ -215,16 +257,20 @@ fn runtime_loop(
             term_size = crossterm::terminal::size().unwrap();
         }
 
-        input = match input::get_input() {
-            Ok(inp) => inp,
-            Err(_) => continue,
+        // While hints are generated in the background, wake up now and then to draw them
+        input = if buffer.hints_pending() {
+            match input::poll_input(HINT_POLL_INTERVAL) {
+                Ok(Some(inp)) => inp,
+                Ok(None) if buffer.receive_hints() => InputEvent::Dummy,
+                Ok(None) | Err(_) => continue,
+            }
+        } else {
+            match input::get_input() {
+                Ok(inp) => inp,
+                Err(_) => continue,
+            }
         };
 
-        if input == InputEvent::CtrlC {
-            ansi::move_down(1);
-            break;
-        }
-
         positions = terminal_gui.calculate_increased_length(&buffer, term_size);
         write_from_line = positions.1 .1 + 1;
 
@@ -239,7 +285,9 @@ fn runtime_loop(
 
         if let gui::ActionToTake::WriteBuffer(action) = action_to_take {
             if let gui::ActionType::Other(other) = action {
-                execute_action(other, &mut buffer, &mut argument_parser);
+                let active_view = terminal_gui.view_type();
+                let view = execute_action(other, active_view, &mut buffer, &mut argument_parser);
+                update_view(view, &mut terminal_gui, write_from_line, &program_state);
             } else {
                 let view = update_buffer(
                     input.clone(),
@@ -284,6 +332,9 @@ fn main() {
     let buffer = buffer::InputBuffer::init(program_state.clone());
     let terminal_gui = gui::terminal::TerminalGUI::init(program_state.clone());
 
+    #[cfg(target_os = "linux")]
+    execution::signals::install_handlers();
+
     crossterm::terminal::enable_raw_mode().unwrap();
     runtime_loop(program_state, buffer, terminal_gui, argument_parser);
     crossterm::terminal::disable_raw_mode().unwrap();
@@ -13,6 +13,27 @@ pub enum Argument {
     Flag(*const command::Flag),
     Arg(*const command::SingleArg),
     ArgFlag(*const command::FlagArgPair),
+    /// The word choosing a subcommand of the given command, which may not name one (yet).
+    Subcommand(*const command::ConfigCommand),
+    /// Several flags in one word, or a flag with its value attached, see `FlagGroup`.
+    Flags(FlagGroup),
+    /// `--`, after which no argument is a flag.
+    EndOfFlags,
+    /// A word starting with `-` that isn't one of the command's flags, perhaps as it is still being
+    /// typed.
+    UnknownFlag,
+    /// A positional argument beyond those the command takes.
+    Excess,
+}
+
+/// Short flags clustered after a single `-`, e.g. `-la` or `-xvf archive.tar`, or a flag with its
+/// value attached, e.g. `--output=file` or `-ofile`.
+#[derive(Debug)]
+pub struct FlagGroup {
+    pub flags: Vec<*const command::Flag>,
+    /// The last flag, if it takes a value. The value is attached at the given byte offset, or
+    /// otherwise the next argument.
+    pub arg_flag: Option<(*const command::FlagArgPair, Option<usize>)>,
 }
 
 #[derive(Debug)]
@@ -59,6 +80,9 @@ impl ArgumentParser {
                     }
                 }
             }
+        } else {
+            // E.g. the buffer was cleared
+            self.has_command = false;
         }
     }
 
@@ -78,30 +102,54 @@ impl ArgumentParser {
 #[derive(Debug)]
 pub struct ArgumentIterator<'a> {
     argument_parser: &'a ArgumentParser,
+    /// The command or subcommand the arguments are parsed against.
+    cmd: &'a command::ConfigCommand,
     arg_ind: usize,
     single_argument_count: usize,
+    /// How many arguments the current `SingleArg` has taken, which is only ever more than one if it
+    /// is `variadic`.
+    repeat_count: usize,
     args: Vec<String>,
-    flag_skips: Vec<usize>,
-    arg_flag_skips: Vec<usize>,
+    /// The `flag_name` of each flag given so far, with the argument that gave or implied it, and
+    /// whether it was given rather than implied.
+    seen_flags: Vec<(&'a str, usize, bool)>,
+    /// Arguments that are flags repeated or given with another they exclude.
+    violations: Vec<usize>,
+    /// Required flags of the commands the iterator has moved on from to a subcommand.
+    missing_flags: Vec<&'a str>,
+    /// Set by `--`.
+    end_of_flags: bool,
+    /// The arguments of a subcommand missing from the config aren't known, so can't be excess.
+    unknown_subcommand: bool,
 }
 
 impl<'a> ArgumentIterator<'a> {
     pub fn new(argument_parser: &'a ArgumentParser) -> Self {
         Self {
             argument_parser,
+            cmd: argument_parser.cmd(),
             arg_ind: 0,
             single_argument_count: 0,
+            repeat_count: 0,
             args: vec![],
-            flag_skips: vec![],
-            arg_flag_skips: vec![],
+            seen_flags: vec![],
+            violations: vec![],
+            missing_flags: vec![],
+            end_of_flags: false,
+            unknown_subcommand: false,
         }
     }
 
     pub fn reinit(&mut self, args: Vec<String>) {
+        self.cmd = self.argument_parser.cmd();
         self.arg_ind = 0;
         self.single_argument_count = 0;
-        self.flag_skips.clear();
-        self.arg_flag_skips.clear();
+        self.repeat_count = 0;
+        self.seen_flags.clear();
+        self.violations.clear();
+        self.missing_flags.clear();
+        self.end_of_flags = false;
+        self.unknown_subcommand = false;
         self.args = args;
     }
 
@@ -110,20 +158,11 @@ impl<'a> ArgumentIterator<'a> {
         &mut self,
         arg: &str,
     ) -> Option<Argument> {
-        let k = utils::binary_search_with_exclude(
-            arg,
-            command::FlagArgPair::flag_name,
-            &self.argument_parser.cmd().arg_flags,
-            &self.arg_flag_skips,
-        );
-
-        if let Some(k) = k {
-            // arg_ind += 1 because we want to skip the next arg (pair of flag and arg)
-            self.arg_ind += 1;
-            self.arg_flag_skips.push(k);
-            return Some(Argument::ArgFlag(&self.argument_parser.cmd().arg_flags[k] as *const command::FlagArgPair));
-        }
-        None
+        let arg_flag = self.find_arg_flag(arg)?;
+        self.use_flag(&arg_flag.flag_name, &arg_flag.info, self.arg_ind - 1);
+        // arg_ind += 1 because we want to skip the next arg (pair of flag and arg)
+        self.arg_ind += 1;
+        Some(Argument::ArgFlag(arg_flag as *const command::FlagArgPair))
     }
 
     /// Skip behaviour = Once
@@ -131,34 +170,290 @@ impl<'a> ArgumentIterator<'a> {
         &mut self,
         arg: &str,
     ) -> Option<Argument> {
-        let k = utils::binary_search_with_exclude(
-            arg,
-            command::Flag::flag_name,
-            &self.argument_parser.cmd().flags,
-            &self.flag_skips,
-        );
+        let flag = self.find_flag(arg)?;
+        self.use_flag(&flag.flag_name, &flag.info, self.arg_ind - 1);
+        Some(Argument::Flag(flag as *const command::Flag))
+    }
+
+    /// By `flag_name`, or otherwise any of its aliases.
+    fn find_flag(&self, name: &str) -> Option<&'a command::Flag> {
+        let flags = &self.cmd.flags;
+        utils::binary_search_with_exclude(name, command::Flag::flag_name, flags, &[])
+            .map(|k| &flags[k])
+            .or_else(|| flags.iter().find(|flag| flag.has_name(name)))
+    }
+
+    /// By `flag_name`, or otherwise any of its aliases.
+    fn find_arg_flag(&self, name: &str) -> Option<&'a command::FlagArgPair> {
+        let arg_flags = &self.cmd.arg_flags;
+        utils::binary_search_with_exclude(name, command::FlagArgPair::flag_name, arg_flags, &[])
+            .map(|k| &arg_flags[k])
+            .or_else(|| arg_flags.iter().find(|arg_flag| arg_flag.has_name(name)))
+    }
 
-        if let Some(k) = k {
-            self.flag_skips.push(k);
-            return Some(Argument::Flag(&self.argument_parser.cmd().flags[k] as *const command::Flag));
+    fn find_info(&self, name: &str) -> Option<&'a command::FlagInfo> {
+        self.find_flag(name).map(|flag| &flag.info)
+            .or_else(|| self.find_arg_flag(name).map(|arg_flag| &arg_flag.info))
+    }
+
+    /// Records a flag given by the argument at `word`, along with those it implies, and any rule
+    /// this breaks.
+    fn use_flag(&mut self, name: &'a str, info: &'a command::FlagInfo, word: usize) {
+        let repeated = self.seen_flags.iter().any(|&(seen, _, given)| given && seen == name);
+        if repeated && !info.repeatable {
+            self.violations.push(word);
+        }
+        self.see_flag(name, word, true);
+
+        let mut implied = info.implies.iter().map(String::as_str).collect::<Vec<_>>();
+        while let Some(name) = implied.pop() {
+            // Guards against flags implying each other
+            if self.seen_flags.iter().any(|&(seen, seen_word, _)| seen == name && seen_word == word) {
+                continue;
+            }
+            self.see_flag(name, word, false);
+            if let Some(info) = self.find_info(name) {
+                implied.extend(info.implies.iter().map(String::as_str));
+            }
         }
-        None
     }
 
-    /// Skip behaviour = Once
-    fn process_args(
+    fn see_flag(&mut self, name: &'a str, word: usize, given: bool) {
+        for group in self.cmd.exclusive_flags.iter().filter(|group| group.iter().any(|n| n == name)) {
+            let conflicts = self.seen_flags.iter()
+                .filter(|&&(seen, seen_word, seen_given)| {
+                    // A flag implied by this same word is not a conflict of the user's making
+                    seen != name && (seen_word != word || seen_given) && group.iter().any(|n| n == seen)
+                })
+                .map(|&(_, seen_word, _)| seen_word)
+                .collect::<Vec<_>>();
+            if !conflicts.is_empty() {
+                self.violations.extend(conflicts);
+                self.violations.push(word);
+            }
+        }
+        self.seen_flags.push((name, word, given));
+    }
+
+    /// The required flags of the current command that haven't been given or implied.
+    fn required_flags(&self) -> impl Iterator<Item = &'a str> + '_ {
+        let cmd = self.cmd;
+        cmd.flags.iter().map(|flag| (&flag.flag_name, &flag.info))
+            .chain(cmd.arg_flags.iter().map(|arg_flag| (&arg_flag.flag_name, &arg_flag.info)))
+            .filter(|(_, info)| info.required)
+            .map(|(name, _)| name.as_str())
+            .filter(|name| !self.seen_flags.iter().any(|&(seen, _, _)| seen == *name))
+    }
+
+    /// Follows the POSIX and GNU conventions for what `process_flags` and `process_arg_flags` don't
+    /// match as a whole.
+    fn process_flag_group(
         &mut self,
+        arg: &str,
+    ) -> Option<Argument> {
+        let word = self.arg_ind - 1;
+        if let Some(long) = arg.strip_prefix("--") {
+            let (name, _) = long.split_once('=')?;
+            let arg_flag = self.find_arg_flag(&arg[..name.len() + 2])?;
+            self.use_flag(&arg_flag.flag_name, &arg_flag.info, word);
+            return Some(Argument::Flags(FlagGroup {
+                flags: vec![],
+                arg_flag: Some((arg_flag as *const command::FlagArgPair, Some(name.len() + 3))),
+            }));
+        }
+
+        let cluster = arg.strip_prefix('-').filter(|cluster| !cluster.is_empty())?;
+        let mut flags = vec![];
+        let mut group = FlagGroup { flags: vec![], arg_flag: None };
+        for (i, c) in cluster.char_indices() {
+            let name = format!("-{}", c);
+            if let Some(flag) = self.find_flag(&name) {
+                flags.push((&flag.flag_name, &flag.info));
+                group.flags.push(flag as *const command::Flag);
+            } else if let Some(arg_flag) = self.find_arg_flag(&name) {
+                // The rest of the word is its value, if there is any
+                let value_start = 1 + i + c.len_utf8();
+                let value = (value_start < arg.len()).then_some(value_start);
+                if value.is_none() {
+                    self.arg_ind += 1;
+                }
+                flags.push((&arg_flag.flag_name, &arg_flag.info));
+                group.arg_flag = Some((arg_flag as *const command::FlagArgPair, value));
+                break;
+            } else {
+                // Perhaps a negative number, or a flag missing from the config
+                return None;
+            }
+        }
+        for (name, info) in flags {
+            self.use_flag(name, info, word);
+        }
+        Some(Argument::Flags(group))
+    }
+
+    /// The first positional argument of a command with subcommands chooses one, and the rest are
+    /// parsed against it. Otherwise, the command takes no positional arguments of its own.
+    fn process_subcommand(
+        &mut self,
+        arg: &str,
     ) -> Option<Argument> {
-        if self.single_argument_count == self.argument_parser.cmd().args.len() {
+        if self.cmd.subcommands.is_empty() || self.single_argument_count != 1 {
             return None;
         }
 
-        let arg = &self.argument_parser.cmd().args[self.single_argument_count];
-        self.single_argument_count += 1;
-        return Some(Argument::Arg(arg as *const command::SingleArg));
+        let parent = self.cmd;
+        match parent.subcommand(arg) {
+            Some(subcommand) => {
+                let missing = self.required_flags().collect::<Vec<_>>();
+                self.missing_flags.extend(missing);
+                self.seen_flags.clear();
+                self.cmd = subcommand;
+                // Its first argument is the subcommand itself, see `command::normalize`
+                self.single_argument_count = 1;
+                self.repeat_count = 0;
+            }
+            None => {
+                self.single_argument_count = parent.args.len();
+                self.unknown_subcommand = true;
+            }
+        }
+        Some(Argument::Subcommand(parent as *const command::ConfigCommand))
+    }
+
+    /// Whether `arg` is read as a flag, and if so, whether it takes the next argument as its value.
+    /// Mirrors how `next` tells flags apart, without taking them.
+    fn flag_takes_next(&self, arg: &str) -> Option<bool> {
+        if self.find_arg_flag(arg).is_some() {
+            return Some(true);
+        }
+        if self.find_flag(arg).is_some() {
+            return Some(false);
+        }
+        if !looks_like_flag(arg) {
+            return None;
+        }
+
+        // Only a cluster ending in a flag that takes an argument can take the next one
+        if !arg.starts_with("--") {
+            for (i, c) in arg.char_indices().skip(1) {
+                let name = format!("-{}", c);
+                if self.find_arg_flag(&name).is_some() {
+                    return Some(i + c.len_utf8() == arg.len());
+                }
+                if self.find_flag(&name).is_none() {
+                    break;
+                }
+            }
+        }
+        Some(false)
+    }
+
+    /// Arguments that are flags repeated or given with another they exclude, once the iterator has
+    /// been consumed.
+    pub fn violations(&self) -> &[usize] {
+        &self.violations
+    }
+
+    /// The `flag_name`s of the required flags not given, once the iterator has been consumed.
+    pub fn missing_flags(&self) -> Vec<&'a str> {
+        let mut missing = self.missing_flags.clone();
+        missing.extend(self.required_flags());
+        missing
+    }
+
+    /// The command or subcommand the arguments are currently parsed against.
+    pub fn cmd(&self) -> &'a command::ConfigCommand {
+        self.cmd
+    }
+
+    /// Whether none of the arguments after the current one are positional.
+    fn is_last_positional(&self) -> bool {
+        let mut end_of_flags = self.end_of_flags;
+        let mut rest = self.args[self.arg_ind..].iter();
+        while let Some(arg) = rest.next() {
+            if end_of_flags {
+                return false;
+            }
+            if arg == "--" {
+                end_of_flags = true;
+                continue;
+            }
+            match self.flag_takes_next(arg) {
+                Some(true) => { rest.next(); }
+                Some(false) => (),
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Whether the arguments up to the `last` have all taken as many as they need.
+    fn has_required_args(&self) -> bool {
+        let args = &self.cmd.args;
+        match args.get(self.single_argument_count) {
+            Some(arg) if !arg.last => {
+                arg.min_count() <= self.repeat_count
+                    && args[self.single_argument_count + 1..].iter()
+                        .all(|arg| arg.last || arg.min_count() == 0)
+            }
+            _ => true,
+        }
+    }
+
+    /// Skip behaviour = Once, or up to `max_count` for a `variadic` argument
+    fn process_args(
+        &mut self,
+    ) -> Option<Argument> {
+        let args = &self.cmd.args;
+        if let Some(last) = args.last().filter(|arg| arg.last) {
+            if self.single_argument_count < args.len() && self.has_required_args() && self.is_last_positional() {
+                self.single_argument_count = args.len();
+                self.repeat_count = 0;
+                return Some(Argument::Arg(last as *const command::SingleArg));
+            }
+        }
+
+        let arg = args.get(self.single_argument_count).filter(|arg| !arg.last)?;
+        self.repeat_count += 1;
+        if arg.max_count().is_some_and(|max| self.repeat_count >= max) {
+            self.single_argument_count += 1;
+            self.repeat_count = 0;
+        }
+        Some(Argument::Arg(arg as *const command::SingleArg))
+    }
+
+    /// What a positional argument is once the command takes no more. A command or subcommand with
+    /// no positional arguments in its config isn't checked, as they are often left out.
+    fn unexpected(&self) -> Argument {
+        if self.unknown_subcommand || self.cmd.args.len() <= 1 {
+            Argument::Other
+        } else {
+            Argument::Excess
+        }
+    }
+
+    /// The arguments still needed by the command, once the iterator has been consumed.
+    pub fn missing_args(&self) -> Vec<&'a command::SingleArg> {
+        if self.unknown_subcommand {
+            return vec![];
+        }
+        let mut missing = vec![];
+        for (i, arg) in self.cmd.args.iter().enumerate().skip(self.single_argument_count) {
+            let taken = if i == self.single_argument_count { self.repeat_count } else { 0 };
+            if arg.min_count() > taken {
+                missing.push(arg);
+            }
+        }
+        missing
     }
 }
 
+/// A lone `-` usually stands for stdin, and `-1` is more likely a number than a flag.
+fn looks_like_flag(arg: &str) -> bool {
+    let mut chars = arg.chars();
+    chars.next() == Some('-') && chars.next().is_some_and(|c| !c.is_ascii_digit())
+}
+
 /// Note that `arg_ind` plays a large role in this iterator. It is used to keep track of the current
 /// argument that is being processed and is responsible for skipping arguments.
 impl<'a> Iterator for ArgumentIterator<'a> {
@@ -168,7 +463,8 @@ impl<'a> Iterator for ArgumentIterator<'a> {
             return None;
         }
 
-        if self.arg_ind == self.args.len() {
+        // A flag taking an argument skips past the end if it is the last argument
+        if self.arg_ind >= self.args.len() {
             return None;
         }
 
@@ -183,6 +479,14 @@ impl<'a> Iterator for ArgumentIterator<'a> {
 
         self.arg_ind += 1;
 
+        if self.end_of_flags {
+            return Some(self.process_args().unwrap_or_else(|| self.unexpected()));
+        }
+        if arg == "--" {
+            self.end_of_flags = true;
+            return Some(Argument::EndOfFlags);
+        }
+
         let rtn = self.process_arg_flags(&arg);
         if rtn.is_some() {
             return rtn;
@@ -193,11 +497,25 @@ impl<'a> Iterator for ArgumentIterator<'a> {
             return rtn;
         }
 
+        let rtn = self.process_flag_group(&arg);
+        if rtn.is_some() {
+            return rtn;
+        }
+
+        if looks_like_flag(&arg) {
+            return Some(Argument::UnknownFlag);
+        }
+
+        let rtn = self.process_subcommand(&arg);
+        if rtn.is_some() {
+            return rtn;
+        }
+
         let rtn = self.process_args();
         if rtn.is_some() {
             return rtn;
         }
 
-        return Some(Argument::Other);
+        Some(self.unexpected())
     }
 }
@@ -1,11 +1,23 @@
-use std::path;
-use crate::{config, enums};
+use std::{path, process, time};
+use crate::{config, enums, history, tabular, utils};
 
 #[derive(Debug)]
 pub struct ProgramState {
     pub config: config::FullConfig,
     pub current_working_directory: path::PathBuf,
     pub current_shell: enums::Shell,
+    /// Identifies this process' entries in the shared history.
+    pub session_id: String,
+    pub hostname: String,
+    /// Set by the `table` command, and taken by `gui::records::RecordsGUI` when it opens.
+    pub pending_table: Option<tabular::Table>,
+    /// Commands stopped by e.g. `Ctrl+Z`, as `(pid, command)`, most recent last.
+    pub suspended_jobs: Vec<(u32, String)>,
+    /// Generation of the history indices `history list` last printed, which `history delete`
+    /// checks its indices against.
+    pub listed_history_generation: Option<u64>,
+    /// Which entries history navigation and search go through.
+    pub history_scope: history::ScopeKind,
 }
 
 impl ProgramState {
@@ -14,10 +26,20 @@ impl ProgramState {
         current_working_directory: path::PathBuf,
         current_shell: enums::Shell,
     ) -> Self {
+        let started_at = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
         Self {
+            history_scope: config.history.default_scope,
             config,
             current_working_directory,
             current_shell,
+            session_id: format!("{:x}-{:x}", process::id(), started_at),
+            hostname: utils::hostname(),
+            pending_table: None,
+            suspended_jobs: Vec::new(),
+            listed_history_generation: None,
         }
     }
 }
@@ -0,0 +1,255 @@
+//! Parses command output into rows and columns so it can be explored with `gui::records`.
+//! Supported inputs are JSON arrays, CSV, TSV and whitespace aligned columns (`ls -l`, `ps`).
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Json,
+    Csv,
+    Tsv,
+    Aligned,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl Table {
+    pub fn num_cols(&self) -> usize {
+        self.headers.len()
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Pads short rows so every row has exactly one cell per header.
+    fn normalise(mut self) -> Self {
+        let cols = self
+            .rows
+            .iter()
+            .map(|r| r.len())
+            .max()
+            .unwrap_or(0)
+            .max(self.headers.len());
+        for i in self.headers.len()..cols {
+            self.headers.push(i.to_string());
+        }
+        for row in self.rows.iter_mut() {
+            row.resize(cols, String::new());
+        }
+        self
+    }
+}
+
+/// Column names used for `ls -l` style output, which does not come with a header line.
+const LS_HEADERS: &[&str] = &[
+    "mode", "links", "owner", "group", "size", "month", "day", "time", "name",
+];
+
+pub fn detect_format(input: &str) -> Format {
+    let trimmed = input.trim_start();
+    if trimmed.starts_with('[') {
+        return Format::Json;
+    }
+
+    let mut lines = trimmed.lines().filter(|l| !l.trim().is_empty());
+    let first = lines.next().unwrap_or_default();
+    if first.contains('\t') {
+        return Format::Tsv;
+    }
+
+    let commas = |line: &str| split_delimited_line(line, ',').len();
+    if first.contains(',') {
+        match lines.next() {
+            Some(second) if commas(second) == commas(first) => return Format::Csv,
+            None => return Format::Csv,
+            _ => (),
+        }
+    }
+    Format::Aligned
+}
+
+pub fn parse(input: &str) -> Option<Table> {
+    let table = match detect_format(input) {
+        Format::Json => parse_json(input).or_else(|| parse_aligned(input)),
+        Format::Csv => parse_delimited(input, ','),
+        Format::Tsv => parse_delimited(input, '\t'),
+        Format::Aligned => parse_aligned(input),
+    }?;
+    if table.headers.is_empty() {
+        return None;
+    }
+    Some(table.normalise())
+}
+
+fn json_to_cell(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.clone(),
+        v => v.to_string(),
+    }
+}
+
+pub fn parse_json(input: &str) -> Option<Table> {
+    let value: serde_json::Value = serde_json::from_str(input).ok()?;
+    let items = value.as_array()?;
+
+    let mut headers: Vec<String> = vec![];
+    for item in items {
+        if let serde_json::Value::Object(map) = item {
+            for key in map.keys() {
+                if !headers.contains(key) {
+                    headers.push(key.clone());
+                }
+            }
+        }
+    }
+
+    let rows = items
+        .iter()
+        .map(|item| match item {
+            serde_json::Value::Object(map) => headers
+                .iter()
+                .map(|h| map.get(h).map(json_to_cell).unwrap_or_default())
+                .collect(),
+            serde_json::Value::Array(values) => values.iter().map(json_to_cell).collect(),
+            v => vec![json_to_cell(v)],
+        })
+        .collect::<Vec<Vec<String>>>();
+
+    if headers.is_empty() {
+        // Arrays of arrays or scalars have no names, the columns are numbered instead.
+        let cols = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        headers = match cols {
+            1 => vec!["value".to_string()],
+            n => (0..n).map(|i| i.to_string()).collect(),
+        };
+    }
+
+    Some(Table { headers, rows })
+}
+
+/// Splits a single line, honouring double quotes and `""` escapes.
+fn split_delimited_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut cells = vec![];
+    let mut cell = String::new();
+    let mut in_quote = false;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if in_quote && chars.peek() == Some(&'"') => {
+                cell.push('"');
+                chars.next();
+            }
+            '"' => in_quote = !in_quote,
+            c if c == delimiter && !in_quote => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c),
+        }
+    }
+    cells.push(cell);
+    cells
+}
+
+pub fn parse_delimited(input: &str, delimiter: char) -> Option<Table> {
+    let mut lines = input.lines().filter(|l| !l.trim().is_empty());
+    let headers = split_delimited_line(lines.next()?, delimiter)
+        .into_iter()
+        .map(|h| h.trim().to_string())
+        .collect();
+    let rows = lines
+        .map(|line| split_delimited_line(line, delimiter))
+        .collect();
+    Some(Table { headers, rows })
+}
+
+/// Splits `line` into at most `n` whitespace separated columns. The final column keeps the rest of
+/// the line intact, so file names and commands containing spaces survive.
+fn split_columns(line: &str, n: usize) -> Vec<String> {
+    let mut cols = Vec::with_capacity(n);
+    let mut rest = line.trim();
+    while !rest.is_empty() {
+        if cols.len() + 1 == n {
+            cols.push(rest.to_string());
+            break;
+        }
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        cols.push(rest[..end].to_string());
+        rest = rest[end..].trim_start();
+    }
+    cols
+}
+
+fn is_ls_mode(s: &str) -> bool {
+    let mut chars = s.chars();
+    let file_type = chars.next();
+    matches!(file_type, Some('-' | 'd' | 'l' | 'c' | 'b' | 'p' | 's'))
+        && s.len() >= 10
+        && chars.take(9).all(|c| "rwxsStT-".contains(c))
+}
+
+pub fn parse_aligned(input: &str) -> Option<Table> {
+    let mut lines = input
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter(|l| !l.starts_with("total "))
+        .peekable();
+
+    let first = *lines.peek()?;
+    let headers: Vec<String> = match first.split_whitespace().next() {
+        Some(mode) if is_ls_mode(mode) => LS_HEADERS.iter().map(|h| h.to_string()).collect(),
+        _ => {
+            lines.next();
+            first.split_whitespace().map(|h| h.to_string()).collect()
+        }
+    };
+
+    let rows = lines
+        .map(|line| split_columns(line, headers.len()))
+        .collect();
+    Some(Table { headers, rows })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_format() {
+        assert_eq!(detect_format("[{\"a\": 1}]"), Format::Json);
+        assert_eq!(detect_format("a,b\n1,2"), Format::Csv);
+        assert_eq!(detect_format("a\tb\n1\t2"), Format::Tsv);
+        assert_eq!(detect_format("PID TTY TIME CMD\n1 ? 00:00:01 init"), Format::Aligned);
+        assert_eq!(detect_format("hello, world\nnot csv"), Format::Aligned);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let table = parse("[{\"name\": \"a\", \"size\": 1}, {\"name\": \"b\", \"extra\": null}]").unwrap();
+        assert_eq!(table.headers, vec!["name", "size", "extra"]);
+        assert_eq!(table.rows[0], vec!["a", "1", ""]);
+        assert_eq!(table.rows[1], vec!["b", "", ""]);
+    }
+
+    #[test]
+    fn test_parse_csv_quotes() {
+        let table = parse("name,comment\nfoo,\"a, b\"\nbar,\"say \"\"hi\"\"\"").unwrap();
+        assert_eq!(table.headers, vec!["name", "comment"]);
+        assert_eq!(table.rows[0], vec!["foo", "a, b"]);
+        assert_eq!(table.rows[1], vec!["bar", "say \"hi\""]);
+    }
+
+    #[test]
+    fn test_parse_aligned() {
+        let ps = "  PID TTY          TIME CMD\n    1 ?        00:00:01 sh -c sleep 10\n";
+        let table = parse(ps).unwrap();
+        assert_eq!(table.headers, vec!["PID", "TTY", "TIME", "CMD"]);
+        assert_eq!(table.rows[0], vec!["1", "?", "00:00:01", "sh -c sleep 10"]);
+
+        let ls = "total 8\n-rw-r--r-- 1 sean sean 42 Oct 11 10:00 my file.txt\n";
+        let table = parse(ls).unwrap();
+        assert_eq!(table.num_cols(), LS_HEADERS.len());
+        assert_eq!(table.rows[0][8], "my file.txt");
+    }
+}
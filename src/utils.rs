@@ -1,7 +1,7 @@
 use std::{
     fs,
     io::{self, BufRead},
-    path,
+    path, time,
 };
 
 #[cfg(target_os = "windows")]
@@ -26,6 +26,65 @@ pub fn short_path(full_path: &path::PathBuf) -> String {
     output
 }
 
+/// E.g. `4.2s`, `3m 12s` or `1h 5m`.
+pub fn format_duration(duration: time::Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else if secs < 60 * 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / (60 * 60), (secs / 60) % 60)
+    }
+}
+
+/// Seconds since the unix epoch as `yyyy-mm-dd hh:mm`.
+pub fn format_timestamp(secs: u64) -> String {
+    match chrono::NaiveDateTime::from_timestamp_opt(secs as i64, 0) {
+        Some(t) => t.format("%Y-%m-%d %H:%M").to_string(),
+        None => String::from("-"),
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub fn hostname() -> String {
+    let mut buf = [0u8; 256];
+    let ok = unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == 0;
+    let len = buf.iter().position(|b| *b == 0).unwrap_or(buf.len());
+    match ok {
+        true => String::from_utf8_lossy(&buf[..len]).to_string(),
+        false => String::new(),
+    }
+}
+
+#[cfg(target_os = "windows")]
+pub fn hostname() -> String {
+    std::env::var("COMPUTERNAME").unwrap_or_default()
+}
+
+pub fn home_dir() -> Option<path::PathBuf> {
+    #[cfg(target_os = "windows")]
+    let home = std::env::var_os("USERPROFILE");
+    #[cfg(not(target_os = "windows"))]
+    let home = std::env::var_os("HOME");
+    home.filter(|home| !home.is_empty()).map(path::PathBuf::from)
+}
+
+/// The closest directory at or above `dir` that contains a `.git`.
+pub fn git_root(dir: &path::Path) -> Option<path::PathBuf> {
+    dir.ancestors()
+        .find(|ancestor| ancestor.join(".git").exists())
+        .map(path::Path::to_path_buf)
+}
+
+/// Resolves `~/` against the home directory, and relative paths against `cwd`.
+pub fn resolve_path(p: &str, cwd: &path::Path) -> path::PathBuf {
+    match (p.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => cwd.join(p),
+    }
+}
+
 pub fn read_lines<P>(filename: P) -> io::Result<io::Lines<io::BufReader<fs::File>>>
     where
         P: AsRef<path::Path>,
@@ -45,16 +104,6 @@ pub fn appendable_file<P>(filename: P) -> Result<fs::File, io::Error>
         .open(filename)
 }
 
-pub fn first_item(array: &[String], target: &str) -> Option<usize> {
-    // TODO: This is probably slow
-    for (i, item) in array.iter().enumerate() {
-        if item.starts_with(target) {
-            return Some(i);
-        }
-    }
-    None
-}
-
 // https://shane-o.dev/blog/binary-search-rust
 pub fn binary_search<T: Ord>(k: T, items: &[T]) -> Option<usize> {
     if items.is_empty() {